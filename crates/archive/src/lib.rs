@@ -1,7 +1,3 @@
-#![feature(never_type)]
-#![feature(core_io_borrowed_buf)]
-#![feature(read_buf)]
-
 mod read;
 mod write;
 
@@ -78,6 +74,15 @@ impl Entry {
     pub fn directory(path: impl AsRef<Path>) -> Self {
         Self::new(path, EntryTarget::Directory)
     }
+
+    /// The path of the entry, relative to the root of the archive.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn target(&self) -> &EntryTarget {
+        &self.target
+    }
 }
 
 fn hash_data(hash: &SupportedHash) -> (u8, &[u8]) {
@@ -3,7 +3,7 @@ use std::{
     io::{ErrorKind, Read},
     ops::Range,
     os::unix::prelude::*,
-    task::Poll,
+    task::{ready, Poll},
 };
 
 use bytes::BytesMut;
@@ -371,19 +371,18 @@ impl<'a, T: AsyncRead + Unpin> DataReader<'a, T> {
     ) -> std::task::Poll<std::io::Result<()>> {
         let (reader, buffer) = self.split_borrow();
 
-        let start = buffer.len();
-        let remaining = len.saturating_sub(start);
-        if remaining == 0 {
-            return Poll::Ready(Ok(()));
-        }
-
         let reader = if let Some(reader) = reader.as_mut() {
             reader
         } else {
             return Poll::Ready(Ok(()));
         };
 
-        buffer.poll_extend_from_reader(reader.reader()?, len, cx)
+        // A single read may return fewer bytes than requested, such as at the end of a buffer, so this keeps reading
+        // until everything is available.
+        while buffer.len() < len {
+            ready!(buffer.poll_extend_from_reader(reader.reader()?, len, cx))?;
+        }
+        Poll::Ready(Ok(()))
     }
 }
 
@@ -409,16 +408,12 @@ impl<'a, T: AsyncRead + Unpin> AsyncRead for DataReader<'a, T> {
             return Poll::Ready(Ok(()));
         }
 
-        if self.poll_read_exact(cx, 2).is_pending() {
-            return Poll::Pending;
-        }
+        ready!(self.poll_read_exact(cx, 2))?;
 
         // We need to retain the length at the start so that it's available during the next poll after a partial read.
         let len = u16::from_be_bytes(self.remaining[0..2].try_into().unwrap()) as usize;
         if len == 0 {
-            if self.poll_read_exact(cx, 3).is_pending() {
-                return Poll::Pending;
-            }
+            ready!(self.poll_read_exact(cx, 3))?;
 
             let len = match hash_length(self.remaining[2]) {
                 Ok(v) => v,
@@ -427,9 +422,7 @@ impl<'a, T: AsyncRead + Unpin> AsyncRead for DataReader<'a, T> {
                 }
             };
 
-            if self.poll_read_exact(cx, 3 + len).is_pending() {
-                return Poll::Pending;
-            }
+            ready!(self.poll_read_exact(cx, 3 + len))?;
 
             self.hash = Some(create_hash(
                 self.remaining[2],
@@ -438,9 +431,7 @@ impl<'a, T: AsyncRead + Unpin> AsyncRead for DataReader<'a, T> {
             return Poll::Ready(Ok(()));
         }
 
-        if self.poll_read_exact(cx, 2 + len).is_pending() {
-            return Poll::Pending;
-        }
+        ready!(self.poll_read_exact(cx, 2 + len))?;
 
         self.range = 2..(2 + len);
 
@@ -652,4 +643,58 @@ mod test {
         assert!(reader.next_event()?.is_none());
         Ok(())
     }
+
+    /// Returns at most one byte from each read, like a reader that is split across buffer boundaries.
+    struct OneByte<'a>(&'a [u8]);
+
+    impl tokio::io::AsyncRead for OneByte<'_> {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            if let Some((first, rest)) = self.0.split_first() {
+                buf.put_slice(&[*first]);
+                self.0 = rest;
+            }
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn read_data_async_partial() -> Result {
+        use tokio::io::AsyncReadExt;
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(b"NCK00");
+
+        expected.extend_from_slice(b"\x01");
+        expected.extend_from_slice(b"\x00\x20\x00\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0A\x0B\x0C\x0D\x0E\x0F\x10\x11\x12\x13\x14\x15\x16\x17\x18\x19\x1A\x1B\x1C\x1D\x1E\x1F");
+        expected.extend_from_slice(
+            b"\x00\x11\x20\x21\x22\x23\x24\x25\x26\x27\x28\x29\x2A\x2B\x2C\x2D\x2E\x2F\x30",
+        );
+        expected.extend_from_slice(b"\x00\x00");
+        expected.extend_from_slice(b"\x01\xb7\x83\t\xb3\xd5\xfcWe\xeaO\x02\xa6\xdc\x1d\xfbc7\x01\x90G\0\x11\xf1\x02Sb\xdci\x1e\x17\x88\x95");
+
+        let mut reader = Reader::new(OneByte(expected.as_slice()));
+
+        let hash = match reader.next_event_async().await? {
+            ReadEvent::Data(mut reader) => {
+                let mut buf = Vec::new();
+                reader.read_to_end(&mut buf).await?;
+
+                let data = (0..=48u8).collect::<Vec<_>>();
+                assert_eq!(PrintableBuffer(&data[..]), PrintableBuffer(&buf[..]));
+                reader.hash()
+            }
+            _ => panic!("expected data"),
+        };
+        assert_eq!(
+            Some(SupportedHash::Blake3(*b"\xb7\x83\t\xb3\xd5\xfcWe\xeaO\x02\xa6\xdc\x1d\xfbc7\x01\x90G\0\x11\xf1\x02Sb\xdci\x1e\x17\x88\x95")),
+            hash
+        );
+
+        assert!(reader.next_event_async().await?.is_none());
+        Ok(())
+    }
 }
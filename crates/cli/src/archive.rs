@@ -1,4 +1,6 @@
 mod create;
+mod extract;
+mod list;
 
 use clap::{Args, Subcommand};
 
@@ -15,12 +17,18 @@ pub struct Cli {
 enum Commands {
     #[command(about = "Create a new archive.")]
     Create(create::Cli),
+    #[command(about = "Extract an archive into a directory.")]
+    Extract(extract::Cli),
+    #[command(about = "List the contents of an archive.", visible_alias = "inspect")]
+    List(list::Cli),
 }
 
 impl CommandExec for Cli {
     async fn execute(self) -> anyhow::Result<()> {
         match self.command {
             Commands::Create(v) => v.execute().await,
+            Commands::Extract(v) => v.execute().await,
+            Commands::List(v) => v.execute().await,
         }
     }
 }
@@ -5,7 +5,7 @@ use nck_archive::{Entry, EntryFlags, Writer};
 use nck_hashing::SupportedHasher;
 use tokio::{
     fs::File,
-    io::{AsyncWrite, BufWriter},
+    io::{AsyncWrite, AsyncWriteExt, BufWriter},
 };
 
 use crate::CommandExec;
@@ -108,7 +108,7 @@ impl CommandExec for Cli {
                 }
 
                 nested.sort_unstable();
-                files.extend(nested.into_iter());
+                files.extend(nested);
             } else if stat.is_file() {
                 let open = tokio::fs::OpenOptions::new()
                     .create(false)
@@ -140,6 +140,8 @@ impl CommandExec for Cli {
             writer.write_entry_async(entry).await?;
         }
 
+        // The buffer is not flushed when it is dropped, which would leave the archive truncated.
+        writer.into_inner().flush().await?;
         Ok(())
     }
 }
@@ -0,0 +1,394 @@
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    os::unix::prelude::PermissionsExt,
+    path::{Component, Path, PathBuf},
+};
+
+use clap::Args;
+use nck_archive::{Entry, EntryFlags, EntryTarget, ReadEvent, Reader};
+use nck_hashing::{SupportedHash, SupportedHasher};
+use nck_io::fs::TempDir;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+
+use crate::CommandExec;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Args)]
+#[command(name = "extract", about = "Extracts a nck archive.", long_about = None)]
+pub struct Cli {
+    /// The archive to extract, or `-` to read it from stdin.
+    #[arg(short = 'f', long = "file", default_value = "-")]
+    file: PathBuf,
+
+    /// The directory to extract into, which is created if it does not exist.
+    #[arg(short = 'C', default_value = ".")]
+    directory: PathBuf,
+
+    /// Replace files that already exist.
+    #[arg(long = "force")]
+    force: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum ExtractError {
+    #[error("invalid archive")]
+    Invalid(#[source] std::io::Error),
+    #[error("data in the archive was declared as {declared}, but is {actual}")]
+    Mismatch {
+        declared: SupportedHash,
+        actual: SupportedHash,
+    },
+    #[error("{0:?} is not a relative path within the archive")]
+    UnsafePath(PathBuf),
+    #[error("{0:?} would be written through a symlink")]
+    Symlink(PathBuf),
+    #[error("{path:?} refers to {hash}, which is not in the archive")]
+    Missing { path: PathBuf, hash: SupportedHash },
+    #[error("{0:?} already exists, use --force to replace it")]
+    Exists(PathBuf),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl ExtractError {
+    fn from_read(error: std::io::Error) -> Self {
+        match error.kind() {
+            ErrorKind::InvalidData | ErrorKind::UnexpectedEof => Self::Invalid(error),
+            _ => Self::Io(error),
+        }
+    }
+}
+
+impl CommandExec for Cli {
+    async fn execute(self) -> anyhow::Result<()> {
+        let reader: BufReader<Box<dyn AsyncRead + Send + Unpin>> =
+            if self.file.as_path() == Path::new("-") {
+                BufReader::new(Box::new(tokio::io::stdin()))
+            } else {
+                let open = tokio::fs::OpenOptions::new()
+                    .read(true)
+                    .open(self.file)
+                    .await?;
+                BufReader::new(Box::new(open))
+            };
+
+        extract(reader, &self.directory, self.force).await?;
+        Ok(())
+    }
+}
+
+/// Extracts an archive into a directory.
+///
+/// Data can come after the entries that refer to it, so blobs are staged in a temporary directory under the target and
+/// the entries are only created once the whole archive has been read. Nothing is ever written through a symlink, so
+/// entries cannot escape the target even if the archive contains links that point outside of it.
+pub async fn extract(
+    reader: impl AsyncRead + Unpin + Send,
+    target: &Path,
+    force: bool,
+) -> Result<(), ExtractError> {
+    tokio::fs::create_dir_all(target).await?;
+    let staging = TempDir::new_in(target)?;
+
+    let mut reader = Reader::new(reader);
+    let mut entries = Vec::new();
+    loop {
+        match reader
+            .next_event_async()
+            .await
+            .map_err(ExtractError::from_read)?
+        {
+            ReadEvent::None => break,
+            ReadEvent::Data(mut data) => {
+                let path = staging.as_path().join("partial");
+                let mut file = tokio::fs::File::create(&path).await?;
+                let mut hasher = SupportedHasher::blake3();
+                let mut buf = vec![0u8; CHUNK_SIZE];
+                loop {
+                    let n = data.read(&mut buf).await.map_err(ExtractError::from_read)?;
+                    if n == 0 {
+                        break;
+                    }
+                    hasher.update(&buf[..n]);
+                    file.write_all(&buf[..n]).await?;
+                }
+                file.flush().await?;
+
+                let actual = hasher.finalize();
+                let declared = data
+                    .hash()
+                    .ok_or_else(|| ExtractError::Invalid(ErrorKind::UnexpectedEof.into()))?;
+                if declared != actual {
+                    return Err(ExtractError::Mismatch { declared, actual });
+                }
+                tokio::fs::rename(&path, staging.as_path().join(actual.to_string())).await?;
+            }
+            ReadEvent::Entry(entry) => {
+                check_path(entry.path())?;
+                entries.push(entry);
+            }
+        }
+    }
+
+    // Parents sort before their children, and the last entry to use a blob can take it instead of copying it.
+    entries.sort_unstable_by(|a, b| a.path().cmp(b.path()));
+    let mut uses = BTreeMap::<SupportedHash, usize>::new();
+    for entry in &entries {
+        if let EntryTarget::Data(hash, _) = entry.target() {
+            *uses.entry(*hash).or_default() += 1;
+        }
+    }
+
+    for entry in entries {
+        let path = prepare(target, &entry, force).await?;
+        match entry.target() {
+            EntryTarget::Directory => match tokio::fs::create_dir(&path).await {
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                other => other?,
+            },
+            EntryTarget::Link(source, _) => tokio::fs::symlink(source, &path).await?,
+            EntryTarget::Data(hash, flags) => {
+                let blob = staging.as_path().join(hash.to_string());
+                if !tokio::fs::try_exists(&blob).await? {
+                    return Err(ExtractError::Missing {
+                        path: entry.path().to_path_buf(),
+                        hash: *hash,
+                    });
+                }
+
+                let remaining = uses.get_mut(hash).expect("every use was counted");
+                *remaining -= 1;
+                if *remaining == 0 {
+                    tokio::fs::rename(&blob, &path).await?;
+                } else {
+                    tokio::fs::copy(&blob, &path).await?;
+                }
+
+                let mode = if flags.contains(EntryFlags::EXECUTABLE) {
+                    0o755
+                } else {
+                    0o644
+                };
+                tokio::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).await?;
+            }
+        }
+    }
+
+    staging.delete()?;
+    Ok(())
+}
+
+/// Rejects paths that are absolute or that could leave the target through `..`.
+fn check_path(path: &Path) -> Result<(), ExtractError> {
+    let mut normal = false;
+    for component in path.components() {
+        match component {
+            Component::Normal(_) => normal = true,
+            Component::CurDir => {}
+            Component::RootDir | Component::ParentDir | Component::Prefix(_) => {
+                return Err(ExtractError::UnsafePath(path.to_path_buf()))
+            }
+        }
+    }
+    if !normal {
+        return Err(ExtractError::UnsafePath(path.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// Creates the parents of an entry and clears the way for it, returning where it should be created.
+async fn prepare(target: &Path, entry: &Entry, force: bool) -> Result<PathBuf, ExtractError> {
+    let mut path = target.to_path_buf();
+    let mut components = entry.path().components().peekable();
+    while let Some(component) = components.next() {
+        let Component::Normal(name) = component else {
+            continue;
+        };
+        path.push(name);
+
+        let metadata = match tokio::fs::symlink_metadata(&path).await {
+            Ok(metadata) => Some(metadata),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        if components.peek().is_some() {
+            match metadata {
+                Some(metadata) if metadata.is_dir() => {}
+                Some(metadata) if metadata.is_symlink() => {
+                    return Err(ExtractError::Symlink(entry.path().to_path_buf()))
+                }
+                Some(_) if !force => return Err(ExtractError::Exists(path)),
+                Some(_) => {
+                    tokio::fs::remove_file(&path).await?;
+                    tokio::fs::create_dir(&path).await?;
+                }
+                None => tokio::fs::create_dir(&path).await?,
+            }
+            continue;
+        }
+
+        let Some(metadata) = metadata else {
+            break;
+        };
+        if metadata.is_dir() && matches!(entry.target(), EntryTarget::Directory) {
+            break;
+        }
+        if !force {
+            return Err(ExtractError::Exists(path));
+        }
+        if metadata.is_dir() {
+            tokio::fs::remove_dir_all(&path).await?;
+        } else {
+            tokio::fs::remove_file(&path).await?;
+        }
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io::Write, os::unix::prelude::PermissionsExt, path::Path};
+
+    use nck_archive::{Entry, EntryFlags, Writer};
+    use nck_hashing::{SupportedHash, SupportedHasher};
+    use nck_io::fs::TempDir;
+
+    use super::{extract, ExtractError};
+
+    fn write_data(writer: Writer<Vec<u8>>, data: &[u8]) -> (Writer<Vec<u8>>, SupportedHash) {
+        let mut w = writer.write_data(SupportedHasher::blake3()).unwrap();
+        w.write_all(data).unwrap();
+        w.finish().unwrap()
+    }
+
+    fn archive(entries: impl FnOnce(SupportedHash, SupportedHash) -> Vec<Entry>) -> Vec<u8> {
+        let writer = Writer::new(Vec::new()).unwrap();
+        let (writer, hello) = write_data(writer, b"hello");
+        let (mut writer, script) = write_data(writer, b"#!/bin/sh\n");
+        for entry in entries(hello, script) {
+            writer.write_entry(entry).unwrap();
+        }
+        writer.into_inner()
+    }
+
+    #[tokio::test]
+    async fn extract_entries() {
+        let archive = archive(|hello, script| {
+            vec![
+                Entry::directory("bin"),
+                Entry::data("bin/run", script, Some(EntryFlags::EXECUTABLE)),
+                Entry::data("share/hello", hello, None),
+                Entry::data("share/again", hello, None),
+                Entry::link("share/link", "hello", None),
+            ]
+        });
+        let dir = TempDir::new().unwrap();
+        extract(archive.as_slice(), dir.as_path(), false)
+            .await
+            .unwrap();
+
+        let mode = |path: &str| {
+            std::fs::metadata(dir.as_path().join(path))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        assert_eq!(
+            b"#!/bin/sh\n",
+            &std::fs::read(dir.as_path().join("bin/run")).unwrap()[..]
+        );
+        assert_eq!(0o755, mode("bin/run"));
+        assert_eq!(
+            b"hello",
+            &std::fs::read(dir.as_path().join("share/hello")).unwrap()[..]
+        );
+        assert_eq!(
+            b"hello",
+            &std::fs::read(dir.as_path().join("share/again")).unwrap()[..]
+        );
+        assert_eq!(0o644, mode("share/hello"));
+        assert_eq!(
+            Path::new("hello"),
+            std::fs::read_link(dir.as_path().join("share/link")).unwrap()
+        );
+
+        let mut names: Vec<_> = std::fs::read_dir(dir.as_path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(vec!["bin", "share"], names);
+    }
+
+    #[tokio::test]
+    async fn reject_escaping_paths() {
+        for path in ["../escape", "/escape", "a/../../escape", "."] {
+            let archive = archive(|hello, _| vec![Entry::data(path, hello, None)]);
+            let dir = TempDir::new().unwrap();
+            let error = extract(archive.as_slice(), dir.as_path(), false)
+                .await
+                .unwrap_err();
+            assert!(matches!(error, ExtractError::UnsafePath(_)), "{error:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn reject_writing_through_symlinks() {
+        let outside = TempDir::new().unwrap();
+        let archive = archive(|hello, _| {
+            vec![
+                Entry::link("link", outside.as_path(), None),
+                Entry::data("link/escape", hello, None),
+            ]
+        });
+        let dir = TempDir::new().unwrap();
+        let error = extract(archive.as_slice(), dir.as_path(), true)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ExtractError::Symlink(_)), "{error:?}");
+        assert!(!outside.as_path().join("escape").exists());
+    }
+
+    #[tokio::test]
+    async fn replace_only_when_forced() {
+        let archive = archive(|hello, _| vec![Entry::data("hello", hello, None)]);
+        let dir = TempDir::new().unwrap();
+        std::fs::write(dir.as_path().join("hello"), b"existing").unwrap();
+
+        let error = extract(archive.as_slice(), dir.as_path(), false)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ExtractError::Exists(_)), "{error:?}");
+        assert_eq!(
+            b"existing",
+            &std::fs::read(dir.as_path().join("hello")).unwrap()[..]
+        );
+
+        extract(archive.as_slice(), dir.as_path(), true)
+            .await
+            .unwrap();
+        assert_eq!(
+            b"hello",
+            &std::fs::read(dir.as_path().join("hello")).unwrap()[..]
+        );
+    }
+
+    #[tokio::test]
+    async fn reject_missing_data() {
+        let hash = SupportedHasher::blake3().finalize();
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        writer
+            .write_entry(Entry::data("missing", hash, None))
+            .unwrap();
+        let dir = TempDir::new().unwrap();
+        let error = extract(writer.into_inner().as_slice(), dir.as_path(), false)
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ExtractError::Missing { .. }), "{error:?}");
+    }
+}
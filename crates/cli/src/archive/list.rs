@@ -0,0 +1,317 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use clap::{Args, ValueEnum};
+use nck_archive::{EntryFlags, EntryTarget, ReadEvent, Reader};
+use nck_hashing::{SupportedHash, SupportedHasher};
+use serde::Serialize;
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+
+use crate::CommandExec;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Args)]
+#[command(name = "list", about = "Lists the contents of a nck archive.", long_about = None)]
+pub struct Cli {
+    /// The archive to list, or `-` to read it from stdin.
+    #[arg(short = 'f', long = "file", default_value = "-")]
+    file: PathBuf,
+
+    #[arg(long = "format", default_value = "human")]
+    format: OutputFormat,
+
+    /// Rehash every data blob, and check that every entry refers to a blob in the archive.
+    #[arg(long = "verify")]
+    verify: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    #[value(name = "human")]
+    #[default]
+    Human,
+    #[value(name = "json")]
+    Json,
+}
+
+/// Something in an archive, in the order that it was read.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Item {
+    Data {
+        hash: SupportedHash,
+        size: u64,
+    },
+    File {
+        path: PathBuf,
+        hash: SupportedHash,
+        flags: Vec<String>,
+    },
+    Link {
+        path: PathBuf,
+        target: PathBuf,
+        flags: Vec<String>,
+    },
+    Directory {
+        path: PathBuf,
+    },
+}
+
+/// A problem found while verifying an archive.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Issue {
+    /// The contents of a blob do not match the hash it was declared with.
+    Mismatch {
+        declared: SupportedHash,
+        actual: SupportedHash,
+    },
+    /// An entry refers to data that is not in the archive.
+    Missing { path: PathBuf, hash: SupportedHash },
+}
+
+#[derive(Debug, Serialize)]
+struct Listing {
+    items: Vec<Item>,
+    /// Only present when the archive was verified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    issues: Option<Vec<Issue>>,
+}
+
+impl CommandExec for Cli {
+    async fn execute(self) -> anyhow::Result<()> {
+        let reader: BufReader<Box<dyn AsyncRead + Send + Unpin>> =
+            if self.file.as_path() == Path::new("-") {
+                BufReader::new(Box::new(tokio::io::stdin()))
+            } else {
+                let open = tokio::fs::OpenOptions::new()
+                    .read(true)
+                    .open(self.file)
+                    .await?;
+                BufReader::new(Box::new(open))
+            };
+
+        let listing = inspect(reader, self.verify)
+            .await
+            .context("reading the archive")?;
+
+        match self.format {
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&listing)?),
+            OutputFormat::Human => print_human(&listing),
+        }
+
+        match listing.issues {
+            Some(issues) if !issues.is_empty() => {
+                bail!("the archive has {} issues", issues.len())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Reads every blob and entry in an archive, optionally rehashing the blobs.
+async fn inspect(reader: impl AsyncRead + Unpin + Send, verify: bool) -> std::io::Result<Listing> {
+    let mut reader = Reader::new(reader);
+    let mut items = Vec::new();
+    let mut issues = Vec::new();
+    let mut blobs = BTreeSet::new();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        match reader.next_event_async().await? {
+            ReadEvent::None => break,
+            ReadEvent::Data(mut data) => {
+                let mut hasher = verify.then(SupportedHasher::blake3);
+                let mut size = 0u64;
+                loop {
+                    let n = data.read(&mut buf).await?;
+                    if n == 0 {
+                        break;
+                    }
+                    size += n as u64;
+                    if let Some(hasher) = &mut hasher {
+                        hasher.update(&buf[..n]);
+                    }
+                }
+
+                let declared = data.hash().ok_or(std::io::ErrorKind::UnexpectedEof)?;
+                if let Some(hasher) = hasher {
+                    let actual = hasher.finalize();
+                    if actual != declared {
+                        issues.push(Issue::Mismatch { declared, actual });
+                    }
+                }
+                blobs.insert(declared);
+                items.push(Item::Data {
+                    hash: declared,
+                    size,
+                });
+            }
+            ReadEvent::Entry(entry) => {
+                let path = entry.path().to_path_buf();
+                items.push(match entry.target() {
+                    EntryTarget::Data(hash, flags) => Item::File {
+                        path,
+                        hash: *hash,
+                        flags: flag_names(flags),
+                    },
+                    EntryTarget::Link(target, flags) => Item::Link {
+                        path,
+                        target: target.clone(),
+                        flags: flag_names(flags),
+                    },
+                    EntryTarget::Directory => Item::Directory { path },
+                });
+            }
+        }
+    }
+
+    // Data can come after the entries that refer to it, so entries are only checked once everything has been read.
+    let issues = verify.then(|| {
+        for item in &items {
+            if let Item::File { path, hash, .. } = item {
+                if !blobs.contains(hash) {
+                    issues.push(Issue::Missing {
+                        path: path.clone(),
+                        hash: *hash,
+                    });
+                }
+            }
+        }
+        issues
+    });
+
+    Ok(Listing { items, issues })
+}
+
+fn flag_names(flags: &EntryFlags) -> Vec<String> {
+    flags
+        .iter_names()
+        .map(|(name, _)| name.to_lowercase())
+        .collect()
+}
+
+fn print_human(listing: &Listing) {
+    let flags = |flags: &[String]| {
+        if flags.is_empty() {
+            "-".to_string()
+        } else {
+            flags.join(",")
+        }
+    };
+
+    for item in &listing.items {
+        match item {
+            Item::Data { hash, size } => println!("data      {hash} {size}"),
+            Item::File {
+                path,
+                hash,
+                flags: names,
+            } => println!("file      {} {hash} {}", path.display(), flags(names)),
+            Item::Link {
+                path,
+                target,
+                flags: names,
+            } => println!(
+                "link      {} -> {} {}",
+                path.display(),
+                target.display(),
+                flags(names)
+            ),
+            Item::Directory { path } => println!("directory {}", path.display()),
+        }
+    }
+
+    for issue in listing.issues.iter().flatten() {
+        match issue {
+            Issue::Mismatch { declared, actual } => {
+                println!("mismatch  {declared} (contains {actual})")
+            }
+            Issue::Missing { path, hash } => println!("missing   {} {hash}", path.display()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use nck_archive::{Entry, EntryFlags, Writer};
+    use nck_hashing::SupportedHasher;
+
+    use super::{inspect, Issue, Item};
+
+    #[tokio::test]
+    async fn list_archive() {
+        let writer = Writer::new(Vec::new()).unwrap();
+        let mut data = writer.write_data(SupportedHasher::blake3()).unwrap();
+        data.write_all(b"hello").unwrap();
+        let (mut writer, hash) = data.finish().unwrap();
+        writer.write_entry(Entry::directory("bin")).unwrap();
+        writer
+            .write_entry(Entry::data("bin/hello", hash, Some(EntryFlags::EXECUTABLE)))
+            .unwrap();
+        writer
+            .write_entry(Entry::link("hello", "bin/hello", None))
+            .unwrap();
+        let archive = writer.into_inner();
+
+        let listing = inspect(archive.as_slice(), true).await.unwrap();
+        assert_eq!(
+            vec![
+                Item::Data { hash, size: 5 },
+                Item::Directory { path: "bin".into() },
+                Item::File {
+                    path: "bin/hello".into(),
+                    hash,
+                    flags: vec!["executable".into()],
+                },
+                Item::Link {
+                    path: "hello".into(),
+                    target: "bin/hello".into(),
+                    flags: vec![],
+                },
+            ],
+            listing.items
+        );
+        assert_eq!(Some(vec![]), listing.issues);
+    }
+
+    #[tokio::test]
+    async fn verify_archive() {
+        let missing = SupportedHasher::blake3().finalize();
+        let writer = Writer::new(Vec::new()).unwrap();
+        let mut data = writer.write_data(SupportedHasher::blake3()).unwrap();
+        data.write_all(b"hello").unwrap();
+        let (mut writer, declared) = data.finish().unwrap();
+        writer
+            .write_entry(Entry::data("missing", missing, None))
+            .unwrap();
+        let mut archive = writer.into_inner();
+
+        // Corrupts the first byte of the blob, after the header, the event type and the chunk length.
+        archive[8] = b'j';
+        let mut actual = SupportedHasher::blake3();
+        actual.update(b"jello");
+        let actual = actual.finalize();
+
+        let listing = inspect(archive.as_slice(), false).await.unwrap();
+        assert_eq!(None, listing.issues);
+
+        let listing = inspect(archive.as_slice(), true).await.unwrap();
+        assert_eq!(
+            Some(vec![
+                Issue::Mismatch { declared, actual },
+                Issue::Missing {
+                    path: "missing".into(),
+                    hash: missing,
+                },
+            ]),
+            listing.issues
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+use clap::Args;
+use futures::TryStreamExt;
+use nck_client::{BuildStatus, Client};
+use nck_hashing::{SupportedHash, SupportedHasher};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::{daemon::DaemonArgs, nickel, CommandExec};
+
+/// How long to wait between checks of the status of a build whose log has ended.
+const STATUS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Args)]
+#[command(name = "build", about = "Builds a package with the daemon.", long_about = None)]
+pub struct Cli {
+    #[command(flatten)]
+    daemon: DaemonArgs,
+
+    /// The package to build, either as Nickel (`.ncl`) or as a JSON spec.
+    ///
+    /// Entries in `files` that are not hashes are paths relative to the package, which are uploaded.
+    spec: PathBuf,
+}
+
+impl CommandExec for Cli {
+    async fn execute(self) -> anyhow::Result<()> {
+        let mut spec = load_spec(&self.spec).await?;
+        let base = self
+            .spec
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        let client = self.daemon.client();
+        let build = client.create_build().await.context("creating the build")?;
+
+        let result = submit(&client, &build, &base, &mut spec).await;
+        let hash = match result {
+            Ok(hash) => hash,
+            Err(error) => {
+                if let Err(error) = client.discard_build(&build).await {
+                    eprintln!("failed to discard the build: {error:#}");
+                }
+                return Err(error);
+            }
+        };
+        eprintln!("building {hash}");
+
+        stream_log(&client, &hash).await?;
+        match wait(&client, &hash).await? {
+            BuildStatus::Succeeded { outputs } => {
+                for (name, output) in outputs {
+                    println!("{name} {}", output.path.display());
+                }
+                Ok(())
+            }
+            BuildStatus::Failed {
+                action,
+                exit_code,
+                signal,
+                message,
+            } => {
+                let mut reason = String::from("the build failed");
+                if let Some(action) = action {
+                    reason.push_str(&format!(" at action {action}"));
+                }
+                if let Some(exit_code) = exit_code {
+                    reason.push_str(&format!(" with exit code {exit_code}"));
+                }
+                if let Some(signal) = signal {
+                    reason.push_str(&format!(" with signal {signal}"));
+                }
+                if let Some(message) = message {
+                    reason.push_str(&format!(": {message}"));
+                }
+                bail!(reason)
+            }
+            BuildStatus::Cancelled => bail!("the build was cancelled"),
+            BuildStatus::Queued | BuildStatus::Running => unreachable!("waited for the build"),
+        }
+    }
+}
+
+async fn load_spec(path: &Path) -> anyhow::Result<serde_json::Value> {
+    if path.extension().is_some_and(|extension| extension == "ncl") {
+        let path = path.to_path_buf();
+        let spec = tokio::task::spawn_blocking(move || nickel::evaluate(&path)).await??;
+        return Ok(spec);
+    }
+
+    let spec = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("reading {path:?}"))?;
+    serde_json::from_slice(&spec).with_context(|| format!("parsing {path:?}"))
+}
+
+/// Uploads the local files that the spec refers to and starts the build, returning the hash of the spec.
+async fn submit(
+    client: &Client,
+    build: &str,
+    base: &Path,
+    spec: &mut serde_json::Value,
+) -> anyhow::Result<SupportedHash> {
+    if let Some(files) = spec
+        .get_mut("files")
+        .and_then(serde_json::Value::as_array_mut)
+    {
+        let mut local = Vec::new();
+        for file in files.iter_mut() {
+            let Some(reference) = file.as_str() else {
+                continue;
+            };
+            if reference.parse::<SupportedHash>().is_ok() {
+                continue;
+            }
+            let path = base.join(reference);
+            let hash = hash_file(&path)
+                .await
+                .with_context(|| format!("hashing {path:?}"))?;
+            *file = serde_json::Value::String(hash.to_string());
+            local.push((path, hash));
+        }
+
+        let hashes: Vec<_> = local.iter().map(|(_, hash)| *hash).collect();
+        let missing: BTreeSet<SupportedHash> = client
+            .missing(&hashes)
+            .await
+            .context("querying the store")?
+            .into_iter()
+            .collect();
+        for (path, hash) in local {
+            upload(client, build, &path, &hash, missing.contains(&hash))
+                .await
+                .with_context(|| format!("uploading {path:?}"))?;
+        }
+    }
+
+    client.run(build, spec).await.context("starting the build")
+}
+
+/// Adds a file to the build, only sending its contents if the store is missing it.
+async fn upload(
+    client: &Client,
+    build: &str,
+    path: &Path,
+    hash: &SupportedHash,
+    missing: bool,
+) -> anyhow::Result<()> {
+    // The file may also have been removed from the store since it was queried, in which case it is uploaded anyway.
+    if !missing && client.add_cached_file(build, hash).await? {
+        eprintln!("cached   {} {hash}", path.display());
+        return Ok(());
+    }
+
+    let file = tokio::fs::File::open(path).await?;
+    client.upload_file(build, file, Some(hash)).await?;
+    eprintln!("uploaded {} {hash}", path.display());
+    Ok(())
+}
+
+async fn hash_file(path: &Path) -> anyhow::Result<SupportedHash> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = SupportedHasher::blake3();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(hasher.finalize());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+/// Writes the log of a build to stdout until the build finishes.
+async fn stream_log(client: &Client, hash: &SupportedHash) -> anyhow::Result<()> {
+    let mut log = client.log(hash).await?;
+    let mut stdout = tokio::io::stdout();
+    while let Some(data) = log.try_next().await? {
+        stdout.write_all(&data).await?;
+        stdout.flush().await?;
+    }
+    Ok(())
+}
+
+/// Waits for a build to finish.
+async fn wait(client: &Client, hash: &SupportedHash) -> anyhow::Result<BuildStatus> {
+    loop {
+        let status = client.status(hash).await?;
+        if status.is_finished() {
+            return Ok(status);
+        }
+        tokio::time::sleep(STATUS_INTERVAL).await;
+    }
+}
@@ -0,0 +1,24 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use nck_client::{Client, DEFAULT_SOCKET};
+
+/// How to reach the daemon.
+#[derive(Debug, Args)]
+pub struct DaemonArgs {
+    #[arg(long = "socket", default_value = DEFAULT_SOCKET)]
+    socket: PathBuf,
+
+    /// Connect over TCP to an address such as `localhost:8080`, instead of the Unix socket.
+    #[arg(long = "tcp", conflicts_with = "socket")]
+    tcp: Option<String>,
+}
+
+impl DaemonArgs {
+    pub fn client(&self) -> Client {
+        match &self.tcp {
+            Some(address) => Client::tcp(address),
+            None => Client::unix(&self.socket),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use std::path::PathBuf;
+
+use clap::Args;
+
+use crate::{nickel, CommandExec};
+
+#[derive(Debug, Args)]
+#[command(name = "eval", about = "Evaluates a Nickel package into a build spec.", long_about = None)]
+pub struct Cli {
+    /// The Nickel file that defines the package.
+    file: PathBuf,
+}
+
+impl CommandExec for Cli {
+    async fn execute(self) -> anyhow::Result<()> {
+        let spec = tokio::task::spawn_blocking(move || nickel::evaluate(&self.file)).await??;
+        println!("{}", serde_json::to_string_pretty(&spec)?);
+        Ok(())
+    }
+}
@@ -1,5 +1,10 @@
 mod archive;
+mod build;
+mod daemon;
+mod eval;
 mod hash;
+mod nickel;
+mod store;
 
 use clap::{Parser, Subcommand};
 
@@ -20,8 +25,14 @@ struct Cli {
 enum Commands {
     #[command(about = "Manage nck archives.")]
     Archive(archive::Cli),
+    #[command(about = "Build packages with the daemon.")]
+    Build(build::Cli),
+    #[command(about = "Evaluate Nickel packages.")]
+    Eval(eval::Cli),
     #[command(about = "Hash files.")]
     Hash(hash::Hash),
+    #[command(about = "Manage the daemon's store.")]
+    Store(store::Cli),
 }
 
 #[tokio::main]
@@ -35,6 +46,9 @@ pub async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse_from(args);
     match cli.command {
         Commands::Archive(v) => v.execute().await,
+        Commands::Build(v) => v.execute().await,
+        Commands::Eval(v) => v.execute().await,
         Commands::Hash(v) => v.execute().await,
+        Commands::Store(v) => v.execute().await,
     }
 }
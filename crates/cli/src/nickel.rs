@@ -0,0 +1,132 @@
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use nickel_lang_core::{
+    eval::cache::CacheImpl,
+    program::Program,
+    serialize::{self, ExportFormat},
+};
+use thiserror::Error;
+
+/// The contracts for packages, which are applied to every evaluated file.
+const NICKELPACK: &str = include_str!("nickel/nickelpack.ncl");
+
+#[derive(Debug, Error)]
+pub enum EvalError {
+    #[error("failed to find {path:?}")]
+    Resolve {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("{0:?} is not valid UTF-8")]
+    InvalidPath(PathBuf),
+    /// The program that imports the package could not be loaded, which doesn't depend on the package itself.
+    #[error("failed to load the program that imports the package")]
+    Load(#[source] std::io::Error),
+    /// The diagnostics that Nickel reported, which include the source spans they refer to.
+    #[error("{0}")]
+    Nickel(String),
+    #[error("failed to export the package")]
+    Export(#[from] serde_json::Error),
+}
+
+/// Evaluates a Nickel package into the JSON spec that the daemon builds.
+pub fn evaluate(path: &Path) -> Result<serde_json::Value, EvalError> {
+    let path = std::fs::canonicalize(path).map_err(|source| EvalError::Resolve {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let import = path
+        .to_str()
+        .ok_or_else(|| EvalError::InvalidPath(path.clone()))?;
+    let import = serde_json::to_string(import)?;
+
+    let source =
+        format!("let nickelpack = {NICKELPACK} in\n(import {import}) | nickelpack.Package\n");
+    let mut program = Program::<CacheImpl>::new_from_source(
+        Cursor::new(source),
+        "<nickelpack>",
+        std::io::stderr(),
+    )
+    .map_err(EvalError::Load)?;
+
+    let term = program
+        .eval_full_for_export()
+        .map_err(|error| EvalError::Nickel(program.report_as_str(error)))?;
+    serialize::validate(ExportFormat::Json, &term)
+        .map_err(|error| EvalError::Nickel(program.report_as_str(error)))?;
+    Ok(serde_json::to_value(&term)?)
+}
+
+#[cfg(test)]
+mod test {
+    use nck_io::fs::TempDir;
+    use serde_json::json;
+
+    use super::{evaluate, EvalError};
+
+    const HASH: &str = "blake3-43bvwtdwfeaxy6hfq4mohwlwbl73pr52tntlidmdlpnl2uzmsqhq";
+
+    fn evaluate_source(source: &str) -> Result<serde_json::Value, EvalError> {
+        let dir = TempDir::new().unwrap();
+        let path = dir.as_path().join("package.ncl");
+        std::fs::write(&path, source).unwrap();
+        evaluate(&path)
+    }
+
+    #[test]
+    fn evaluate_package() {
+        let spec = evaluate_source(&format!(
+            r#"
+            let version = "0.0.1" in
+            {{
+              name = "bootstrap-%{{version}}",
+              outputs = ["out"],
+              files = ["{HASH}"],
+              actions = [
+                {{ action = "set", name = "PATH", value = "/bin" }},
+                {{ action = "exec", path = "/bin/sh", args = ["-c", "true"] }},
+              ],
+            }}
+            "#
+        ))
+        .unwrap();
+
+        assert_eq!(
+            json!({
+                "name": "bootstrap-0.0.1",
+                "outputs": ["out"],
+                "files": [HASH],
+                "dependencies": [],
+                "actions": [
+                    { "action": "set", "name": "PATH", "value": "/bin" },
+                    { "action": "exec", "path": "/bin/sh", "args": ["-c", "true"] },
+                ],
+            }),
+            spec
+        );
+    }
+
+    #[test]
+    fn report_contract_violations() {
+        let error = evaluate_source(
+            r#"
+            {
+              name = "bootstrap",
+              outputs = ["out"],
+              actions = [{ action = "work_dir", path = "relative" }],
+            }
+            "#,
+        )
+        .unwrap_err();
+
+        let EvalError::Nickel(report) = error else {
+            panic!("expected a Nickel error, got {error:?}");
+        };
+        assert!(report.contains("contract broken"), "{report}");
+        assert!(report.contains("package.ncl"), "{report}");
+    }
+}
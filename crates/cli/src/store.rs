@@ -0,0 +1,26 @@
+mod verify;
+
+use clap::{Args, Subcommand};
+
+use crate::CommandExec;
+
+#[derive(Debug, Args)]
+#[command(name = "store", about = "Manage the daemon's store.", long_about = None)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    #[command(about = "Check that every store file matches its hash.")]
+    Verify(verify::Cli),
+}
+
+impl CommandExec for Cli {
+    async fn execute(self) -> anyhow::Result<()> {
+        match self.command {
+            Commands::Verify(v) => v.execute().await,
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::bail;
+use clap::Args;
+use nck_client::VerifyIssue;
+
+use crate::{daemon::DaemonArgs, CommandExec};
+
+#[derive(Debug, Args)]
+#[command(name = "verify", about = "Verifies the store.", long_about = None)]
+pub struct Cli {
+    #[command(flatten)]
+    daemon: DaemonArgs,
+
+    /// Move corrupted files and stray entries to the quarantine directory.
+    #[arg(long = "quarantine")]
+    quarantine: bool,
+}
+
+impl CommandExec for Cli {
+    async fn execute(self) -> anyhow::Result<()> {
+        let report = self.daemon.client().verify(self.quarantine).await?;
+
+        for issue in &report.issues {
+            match issue {
+                VerifyIssue::Mismatch { expected, actual } => {
+                    println!("mismatch   {expected} (contains {actual})")
+                }
+                VerifyIssue::Truncated {
+                    expected,
+                    size,
+                    recorded,
+                } => println!("truncated  {expected} ({size} of {recorded} bytes)"),
+                VerifyIssue::Unreadable { expected, error } => {
+                    println!("unreadable {expected} ({error})")
+                }
+                VerifyIssue::Stray { path } => println!("stray      {}", path.display()),
+            }
+        }
+
+        println!(
+            "checked {} files, found {} issues",
+            report.checked,
+            report.issues.len()
+        );
+        if report.issues.is_empty() {
+            Ok(())
+        } else if report.quarantined {
+            bail!("the store had issues, which have been quarantined")
+        } else {
+            bail!("the store has issues, run with --quarantine to move them aside")
+        }
+    }
+}
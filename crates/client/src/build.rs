@@ -0,0 +1,225 @@
+//! The routes under `/api/1/build`, which prepare builds.
+
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+use futures::TryStreamExt;
+use http_body_util::{BodyExt, Empty, StreamBody};
+use hyper::{body::Frame, header, Method, Request, StatusCode};
+use nck_hashing::SupportedHash;
+use serde::Serialize;
+use tokio::io::AsyncRead;
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    multipart::{self, Part},
+    parse_header,
+    types::FetchRequest,
+    Client, ClientError, DaemonError, PendingBuild, UploadedFile, EXPECTED_HASH_HEADER,
+    HASH_HEADER,
+};
+
+const BUILD_PREFIX: &str = "/api/1/build/";
+
+impl Client {
+    /// Creates a build that files can be added to, returning its name.
+    pub async fn create_build(&self) -> Result<String, ClientError> {
+        let (response, _) = self.empty(Method::POST, "/api/1/build").await?;
+        let location: String = parse_header(response.headers(), "Location")?;
+        let name = location
+            .strip_prefix(BUILD_PREFIX)
+            .ok_or(ClientError::Header("Location"))?;
+        Ok(name.to_string())
+    }
+
+    /// Lists the names of the builds that are accepting files.
+    pub async fn builds(&self) -> Result<Vec<String>, ClientError> {
+        self.json(Method::GET, "/api/1/build").await
+    }
+
+    pub async fn build(&self, name: &str) -> Result<PendingBuild, ClientError> {
+        self.json(Method::GET, &format!("{BUILD_PREFIX}{name}"))
+            .await
+    }
+
+    /// Discards a build that has not been run, releasing the files it locked.
+    pub async fn discard_build(&self, name: &str) -> Result<(), ClientError> {
+        self.empty(Method::DELETE, &format!("{BUILD_PREFIX}{name}"))
+            .await?;
+        Ok(())
+    }
+
+    /// Adds a file that might already be in the store to a build, without uploading it.
+    ///
+    /// Returns `false` if the store does not have the file, in which case it has to be uploaded.
+    pub async fn add_cached_file(
+        &self,
+        name: &str,
+        hash: &SupportedHash,
+    ) -> Result<bool, ClientError> {
+        let request = Request::post(format!("{BUILD_PREFIX}{name}/file"))
+            .header(header::IF_NONE_MATCH, format!("\"{hash}\""))
+            .header(EXPECTED_HASH_HEADER, hash.to_string())
+            .body(Empty::<Bytes>::new())?;
+        let response = self.send(request).await?;
+
+        // The empty body only matches the expected hash if the file is empty, which the store doesn't need to have.
+        let status = response.status();
+        let body = response.into_body().collect().await?.to_bytes();
+        match status {
+            StatusCode::SEE_OTHER | StatusCode::CREATED => Ok(true),
+            StatusCode::UNPROCESSABLE_ENTITY => Ok(false),
+            status => Err(DaemonError::from_response(status, &body).into()),
+        }
+    }
+
+    /// Uploads a file to a build, returning its hash.
+    ///
+    /// If an expected hash is given, the daemon discards the file unless it has that hash.
+    pub async fn upload_file<R>(
+        &self,
+        name: &str,
+        reader: R,
+        expected: Option<&SupportedHash>,
+    ) -> Result<SupportedHash, ClientError>
+    where
+        R: AsyncRead + Send + Sync + 'static,
+    {
+        let mut request = Request::post(format!("{BUILD_PREFIX}{name}/file"));
+        if let Some(expected) = expected {
+            request = request.header(EXPECTED_HASH_HEADER, expected.to_string());
+        }
+        let request = request.body(StreamBody::new(
+            ReaderStream::new(reader).map_ok(Frame::data),
+        ))?;
+        let (response, _) = self.checked(request).await?;
+        parse_header(response.headers(), HASH_HEADER)
+    }
+
+    /// Uploads many files to a build in a single request, returning where each part was stored.
+    pub async fn upload_files(
+        &self,
+        name: &str,
+        parts: Vec<Part>,
+    ) -> Result<BTreeMap<String, UploadedFile>, ClientError> {
+        let boundary = multipart::boundary();
+        let body = multipart::body(&boundary, parts)?;
+        let request = Request::post(format!("{BUILD_PREFIX}{name}/file"))
+            .header(
+                header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(StreamBody::new(body.map_ok(Frame::data)))?;
+        let (_, body) = self.checked(request).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Has the daemon download a file into a build, returning its hash once it has been verified.
+    pub async fn fetch(
+        &self,
+        name: &str,
+        url: &str,
+        hash: &SupportedHash,
+    ) -> Result<SupportedHash, ClientError> {
+        let fetch = FetchRequest { url, hash: *hash };
+        let (response, _) = self
+            .with_json(Method::POST, &format!("{BUILD_PREFIX}{name}/fetch"), &fetch)
+            .await?;
+        parse_header(response.headers(), HASH_HEADER)
+    }
+
+    /// Uploads an archive to a build, returning the hash of its manifest.
+    pub async fn upload_archive<R>(
+        &self,
+        name: &str,
+        reader: R,
+    ) -> Result<SupportedHash, ClientError>
+    where
+        R: AsyncRead + Send + Sync + 'static,
+    {
+        let request = Request::post(format!("{BUILD_PREFIX}{name}/archive")).body(
+            StreamBody::new(ReaderStream::new(reader).map_ok(Frame::data)),
+        )?;
+        let (response, _) = self.checked(request).await?;
+        parse_header(response.headers(), HASH_HEADER)
+    }
+
+    /// Submits a build, returning the hash of its spec, which identifies it in the store.
+    pub async fn run(
+        &self,
+        name: &str,
+        spec: &impl Serialize,
+    ) -> Result<SupportedHash, ClientError> {
+        let (response, _) = self
+            .with_json(Method::POST, &format!("{BUILD_PREFIX}{name}/run"), spec)
+            .await?;
+        parse_header(response.headers(), HASH_HEADER)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::StatusCode;
+    use serde_json::json;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use crate::{Client, ClientError};
+
+    const HASH: &str = "blake3-43bvwtdwfeaxy6hfq4mohwlwbl73pr52tntlidmdlpnl2uzmsqhq";
+
+    /// Answers a single request with a canned response, returning the request that was received.
+    async fn serve_once(response: &'static str) -> (Client, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = Client::tcp(listener.local_addr().unwrap().to_string());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            // The requests in these tests are small enough to arrive before the response is needed.
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream.write_all(response.as_bytes()).await.unwrap();
+            stream.shutdown().await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (client, server)
+    }
+
+    #[tokio::test]
+    async fn run_returns_hash() {
+        let (client, server) = serve_once(
+            "HTTP/1.1 201 Created\r\nX-Nickelpack-Hash: blake3-43bvwtdwfeaxy6hfq4mohwlwbl73pr52tntlidmdlpnl2uzmsqhq\r\ncontent-length: 0\r\n\r\n",
+        )
+        .await;
+
+        let hash = client
+            .run("1234", &json!({ "name": "test" }))
+            .await
+            .unwrap();
+        assert_eq!(HASH, hash.to_string());
+
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /api/1/build/1234/run HTTP/1.1\r\n"));
+    }
+
+    #[tokio::test]
+    async fn error_keeps_correlation_id() {
+        let (client, server) = serve_once(
+            "HTTP/1.1 500 Internal Server Error\r\ncontent-type: application/json\r\ncontent-length: 42\r\n\r\n{\"message\":null,\"correlation_id\":\"abc123\"}",
+        )
+        .await;
+
+        let error = client.create_build().await.unwrap_err();
+        server.await.unwrap();
+        let ClientError::Daemon(error) = error else {
+            panic!("expected a daemon error, got {error:?}");
+        };
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, error.status);
+        assert_eq!(Some("abc123".to_string()), error.correlation_id);
+    }
+}
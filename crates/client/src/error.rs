@@ -0,0 +1,139 @@
+use hyper::StatusCode;
+use serde::Deserialize;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    #[error("failed to connect to {address}")]
+    Connect {
+        address: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("failed to communicate with the daemon")]
+    Http(#[from] hyper::Error),
+    #[error("invalid request")]
+    Request(#[from] hyper::http::Error),
+    #[error(transparent)]
+    Daemon(#[from] DaemonError),
+    #[error("the daemon returned invalid JSON")]
+    Json(#[from] serde_json::Error),
+    #[error("the daemon did not return a valid {0} header")]
+    Header(&'static str),
+    #[error("invalid part name {0:?}, names must not contain quotes or line breaks")]
+    PartName(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl ClientError {
+    /// The status that the daemon responded with, if it responded with an error.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Daemon(error) => Some(error.status),
+            _ => None,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.status() == Some(StatusCode::NOT_FOUND)
+    }
+}
+
+/// An error response from the daemon.
+///
+/// Debug builds of the daemon include the causes and a backtrace, whereas release builds include a correlation ID
+/// that identifies the error in the daemon's logs.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub struct DaemonError {
+    pub status: StatusCode,
+    pub message: Option<String>,
+    pub because: Vec<String>,
+    pub trace: Option<String>,
+    pub correlation_id: Option<String>,
+}
+
+/// The union of the debug and release error bodies.
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    message: Option<String>,
+    #[serde(default)]
+    because: Vec<String>,
+    trace: Option<String>,
+    correlation_id: Option<String>,
+}
+
+impl DaemonError {
+    pub(crate) fn from_response(status: StatusCode, body: &[u8]) -> Self {
+        let body = serde_json::from_slice::<ErrorBody>(body).unwrap_or(ErrorBody {
+            message: None,
+            because: Vec::new(),
+            trace: None,
+            correlation_id: None,
+        });
+        Self {
+            status,
+            message: body.message,
+            because: body.because,
+            trace: body.trace,
+            correlation_id: body.correlation_id,
+        }
+    }
+}
+
+impl std::fmt::Display for DaemonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.message {
+            Some(message) => f.write_str(message)?,
+            None => write!(f, "the daemon responded with {}", self.status)?,
+        }
+        for because in &self.because {
+            write!(f, "\n  because: {because}")?;
+        }
+        if let Some(correlation_id) = &self.correlation_id {
+            write!(f, "\n  correlation id: {correlation_id}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::StatusCode;
+
+    use super::DaemonError;
+
+    #[test]
+    fn debug_error() {
+        let error = DaemonError::from_response(
+            StatusCode::BAD_REQUEST,
+            br#"{"message":"invalid If-None-Match value","because":["invalid hash"],"trace":"disabled backtrace"}"#,
+        );
+        assert_eq!(Some("disabled backtrace".to_string()), error.trace);
+        assert_eq!(None, error.correlation_id);
+        assert_eq!(
+            "invalid If-None-Match value\n  because: invalid hash",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn release_error() {
+        let error = DaemonError::from_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            br#"{"message":null,"correlation_id":"abc123"}"#,
+        );
+        assert_eq!(Some("abc123".to_string()), error.correlation_id);
+        assert_eq!(
+            "the daemon responded with 500 Internal Server Error\n  correlation id: abc123",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn unparseable_error() {
+        let error = DaemonError::from_response(StatusCode::BAD_GATEWAY, b"<html>");
+        assert_eq!(StatusCode::BAD_GATEWAY, error.status);
+        assert_eq!(None, error.message);
+    }
+}
@@ -0,0 +1,212 @@
+//! A client for the HTTP API of the Nickelpack daemon.
+//!
+//! Every route of the daemon has a method on [`Client`], which takes care of the headers that the daemon uses to
+//! return hashes and locations, and turns error responses into [`DaemonError`].
+
+use std::path::PathBuf;
+
+use bytes::Bytes;
+use futures::{future, Stream, TryStreamExt};
+use http_body_util::{BodyExt, BodyStream, Empty, Full};
+use hyper::{
+    body::{Body, Incoming},
+    header, HeaderMap, Method, Request, Response,
+};
+use hyper_util::rt::TokioIo;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpStream, UnixStream},
+};
+
+mod build;
+mod error;
+mod multipart;
+mod store;
+mod types;
+
+pub use error::{ClientError, DaemonError};
+pub use multipart::Part;
+pub use types::*;
+
+/// The socket that the daemon listens on by default.
+pub const DEFAULT_SOCKET: &str = "/var/nck/daemon.sock";
+
+/// The header that the daemon uses to return the hash of an uploaded file or a submitted build.
+pub const HASH_HEADER: &str = "X-Nickelpack-Hash";
+
+/// The header that tells the daemon which hash an upload must have.
+pub const EXPECTED_HASH_HEADER: &str = "X-Nickelpack-Expected-Hash";
+
+/// How to reach the daemon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Transport {
+    Unix(PathBuf),
+    /// An address such as `localhost:8080`.
+    Tcp(String),
+}
+
+/// A connection to the daemon.
+///
+/// Each request uses a new connection, so a client is cheap to clone and can be shared between tasks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Client {
+    transport: Transport,
+}
+
+impl Client {
+    /// Connects to the daemon over a Unix socket.
+    pub fn unix(path: impl Into<PathBuf>) -> Self {
+        Self {
+            transport: Transport::Unix(path.into()),
+        }
+    }
+
+    /// Connects to the daemon over TCP, to an address such as `localhost:8080`.
+    pub fn tcp(address: impl Into<String>) -> Self {
+        Self {
+            transport: Transport::Tcp(address.into()),
+        }
+    }
+
+    /// Sends a request to the daemon, returning the response without reading its body or checking its status.
+    pub async fn send<B>(&self, request: Request<B>) -> Result<Response<Incoming>, ClientError>
+    where
+        B: Body + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        match &self.transport {
+            Transport::Tcp(address) => {
+                let stream =
+                    TcpStream::connect(address)
+                        .await
+                        .map_err(|source| ClientError::Connect {
+                            address: address.clone(),
+                            source,
+                        })?;
+                send_over(stream, address, request).await
+            }
+            Transport::Unix(path) => {
+                let stream =
+                    UnixStream::connect(path)
+                        .await
+                        .map_err(|source| ClientError::Connect {
+                            address: path.display().to_string(),
+                            source,
+                        })?;
+                send_over(stream, "localhost", request).await
+            }
+        }
+    }
+
+    /// Sends a request, failing if the status is not successful, and returns the response once its body has been
+    /// read.
+    async fn checked<B>(&self, request: Request<B>) -> Result<(Response<()>, Bytes), ClientError>
+    where
+        B: Body + Send + 'static,
+        B::Data: Send,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        read(self.send(request).await?).await
+    }
+
+    /// Sends a request without a body.
+    async fn empty(
+        &self,
+        method: Method,
+        path: &str,
+    ) -> Result<(Response<()>, Bytes), ClientError> {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Empty::<Bytes>::new())?;
+        self.checked(request).await
+    }
+
+    /// Sends a request without a body, and decodes the JSON response.
+    async fn json<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+    ) -> Result<T, ClientError> {
+        let (_, body) = self.empty(method, path).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Sends a JSON body.
+    async fn with_json(
+        &self,
+        method: Method,
+        path: &str,
+        value: &impl Serialize,
+    ) -> Result<(Response<()>, Bytes), ClientError> {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(serde_json::to_vec(value)?)))?;
+        self.checked(request).await
+    }
+
+    /// Sends a request without a body, and streams the response.
+    async fn stream(
+        &self,
+        path: &str,
+    ) -> Result<impl Stream<Item = Result<Bytes, ClientError>> + Send + Unpin, ClientError> {
+        let request = Request::get(path).body(Empty::<Bytes>::new())?;
+        let response = self.send(request).await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.into_body().collect().await?.to_bytes();
+            return Err(DaemonError::from_response(status, &body).into());
+        }
+        // Trailers are not used by the daemon, so only data frames are kept.
+        Ok(
+            TryStreamExt::map_err(BodyStream::new(response.into_body()), ClientError::from)
+                .try_filter_map(|frame| future::ready(Ok(frame.into_data().ok()))),
+        )
+    }
+}
+
+async fn send_over<S, B>(
+    stream: S,
+    host: &str,
+    mut request: Request<B>,
+) -> Result<Response<Incoming>, ClientError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    B: Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let (mut sender, connection) =
+        hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(connection);
+
+    let host = header::HeaderValue::from_str(host).map_err(hyper::http::Error::from)?;
+    request.headers_mut().insert(header::HOST, host);
+    Ok(sender.send_request(request).await?)
+}
+
+/// Reads the body of a response, failing with the error from the daemon if the status is not successful.
+async fn read(response: Response<Incoming>) -> Result<(Response<()>, Bytes), ClientError> {
+    let (parts, body) = response.into_parts();
+    let body = body.collect().await?.to_bytes();
+    if !parts.status.is_success() {
+        return Err(DaemonError::from_response(parts.status, &body).into());
+    }
+    Ok((Response::from_parts(parts, ()), body))
+}
+
+/// Parses a header that the daemon is expected to have returned.
+fn parse_header<T: std::str::FromStr>(
+    headers: &HeaderMap,
+    name: &'static str,
+) -> Result<T, ClientError> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .ok_or(ClientError::Header(name))
+}
@@ -0,0 +1,120 @@
+//! Streaming `multipart/form-data` bodies, for uploading many files in one request.
+
+use bytes::Bytes;
+use futures::{
+    stream::{self, BoxStream},
+    StreamExt,
+};
+use nck_hashing::SupportedHash;
+use tokio::io::AsyncRead;
+use tokio_util::io::ReaderStream;
+
+use crate::{ClientError, EXPECTED_HASH_HEADER};
+
+/// A file in a multipart upload.
+pub struct Part {
+    name: String,
+    reader: Box<dyn AsyncRead + Send + Sync + Unpin>,
+    expected: Option<SupportedHash>,
+}
+
+impl Part {
+    /// Creates a part, which the daemon reports under `name`.
+    pub fn new(
+        name: impl Into<String>,
+        reader: impl AsyncRead + Send + Sync + Unpin + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            reader: Box::new(reader),
+            expected: None,
+        }
+    }
+
+    /// Has the daemon reject the upload unless the part has this hash.
+    pub fn expected(mut self, hash: SupportedHash) -> Self {
+        self.expected = Some(hash);
+        self
+    }
+}
+
+impl std::fmt::Debug for Part {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Part")
+            .field("name", &self.name)
+            .field("expected", &self.expected)
+            .finish_non_exhaustive()
+    }
+}
+
+pub(crate) fn boundary() -> String {
+    format!("nickelpack-{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Streams the parts, one after the other, without reading them into memory.
+pub(crate) fn body(
+    boundary: &str,
+    parts: Vec<Part>,
+) -> Result<BoxStream<'static, std::io::Result<Bytes>>, ClientError> {
+    let mut streams = Vec::with_capacity(parts.len());
+    for part in parts {
+        if part.name.contains(['"', '\r', '\n']) {
+            return Err(ClientError::PartName(part.name));
+        }
+
+        let mut head = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"\r\n",
+            part.name
+        );
+        if let Some(expected) = part.expected {
+            head.push_str(&format!("{EXPECTED_HASH_HEADER}: {expected}\r\n"));
+        }
+        head.push_str("\r\n");
+
+        let stream = stream::once(async move { Ok(Bytes::from(head)) })
+            .chain(ReaderStream::new(part.reader))
+            .chain(stream::once(async { Ok(Bytes::from_static(b"\r\n")) }));
+        streams.push(stream.boxed());
+    }
+
+    let tail = Bytes::from(format!("--{boundary}--\r\n"));
+    Ok(stream::iter(streams)
+        .flatten()
+        .chain(stream::once(async move { Ok(tail) }))
+        .boxed())
+}
+
+#[cfg(test)]
+mod test {
+    use futures::TryStreamExt;
+
+    use super::{body, Part};
+    use crate::ClientError;
+
+    #[tokio::test]
+    async fn encode_parts() {
+        let parts = vec![
+            Part::new("a", &b"hello"[..]),
+            Part::new("b", &b""[..]).expected(
+                "blake3-43bvwtdwfeaxy6hfq4mohwlwbl73pr52tntlidmdlpnl2uzmsqhq"
+                    .parse()
+                    .unwrap(),
+            ),
+        ];
+        let chunks: Vec<_> = body("xyz", parts).unwrap().try_collect().await.unwrap();
+
+        assert_eq!(
+            "--xyz\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nhello\r\n\
+             --xyz\r\nContent-Disposition: form-data; name=\"b\"\r\n\
+             X-Nickelpack-Expected-Hash: blake3-43bvwtdwfeaxy6hfq4mohwlwbl73pr52tntlidmdlpnl2uzmsqhq\r\n\r\n\r\n\
+             --xyz--\r\n",
+            String::from_utf8(chunks.concat()).unwrap()
+        );
+    }
+
+    #[test]
+    fn reject_quoted_names() {
+        let result = body("xyz", vec![Part::new("a\"b", &b""[..])]);
+        assert!(matches!(result, Err(ClientError::PartName(_))));
+    }
+}
@@ -0,0 +1,108 @@
+//! The routes under `/api/1/store` and `/api/1/download`, which query and maintain the store.
+
+use std::collections::BTreeMap;
+
+use bytes::Bytes;
+use futures::Stream;
+use hyper::{Method, StatusCode};
+use nck_hashing::SupportedHash;
+
+use crate::{
+    types::RootTarget, BuildRecord, BuildStatus, Client, ClientError, FileRecord, GcReport,
+    VerifyReport,
+};
+
+impl Client {
+    pub async fn status(&self, hash: &SupportedHash) -> Result<BuildStatus, ClientError> {
+        self.json(Method::GET, &format!("/api/1/store/status/{hash}"))
+            .await
+    }
+
+    /// Streams the log of a build, which ends when the build finishes.
+    pub async fn log(
+        &self,
+        hash: &SupportedHash,
+    ) -> Result<impl Stream<Item = Result<Bytes, ClientError>> + Send + Unpin, ClientError> {
+        self.stream(&format!("/api/1/store/log/{hash}")).await
+    }
+
+    pub async fn build_record(&self, hash: &SupportedHash) -> Result<BuildRecord, ClientError> {
+        self.json(Method::GET, &format!("/api/1/store/build/{hash}"))
+            .await
+    }
+
+    /// Cancels a build that is queued or running.
+    pub async fn cancel(&self, hash: &SupportedHash) -> Result<(), ClientError> {
+        self.empty(Method::DELETE, &format!("/api/1/store/build/{hash}"))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn file_record(&self, hash: &SupportedHash) -> Result<FileRecord, ClientError> {
+        self.json(Method::GET, &format!("/api/1/store/file/{hash}"))
+            .await
+    }
+
+    /// Lists the files that the store doesn't have.
+    pub async fn missing(
+        &self,
+        hashes: &[SupportedHash],
+    ) -> Result<Vec<SupportedHash>, ClientError> {
+        let (_, body) = self
+            .with_json(Method::POST, "/api/1/store/missing", &hashes)
+            .await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Removes everything that is not reachable from a root, or only reports it when `dry_run` is set.
+    pub async fn collect_garbage(&self, dry_run: bool) -> Result<GcReport, ClientError> {
+        self.json(Method::POST, &format!("/api/1/store/gc?dry_run={dry_run}"))
+            .await
+    }
+
+    /// Rehashes every file in the store, optionally moving anything wrong to the quarantine directory.
+    pub async fn verify(&self, quarantine: bool) -> Result<VerifyReport, ClientError> {
+        self.json(
+            Method::POST,
+            &format!("/api/1/store/verify?quarantine={quarantine}"),
+        )
+        .await
+    }
+
+    pub async fn roots(&self) -> Result<BTreeMap<String, SupportedHash>, ClientError> {
+        self.json(Method::GET, "/api/1/store/roots").await
+    }
+
+    pub async fn root(&self, name: &str) -> Result<SupportedHash, ClientError> {
+        let target: RootTarget = self
+            .json(Method::GET, &format!("/api/1/store/roots/{name}"))
+            .await?;
+        Ok(target.hash)
+    }
+
+    /// Points a root at a file, returning `true` if the root was created rather than replaced.
+    pub async fn set_root(&self, name: &str, hash: &SupportedHash) -> Result<bool, ClientError> {
+        let (response, _) = self
+            .with_json(
+                Method::PUT,
+                &format!("/api/1/store/roots/{name}"),
+                &RootTarget { hash: *hash },
+            )
+            .await?;
+        Ok(response.status() == StatusCode::CREATED)
+    }
+
+    pub async fn remove_root(&self, name: &str) -> Result<(), ClientError> {
+        self.empty(Method::DELETE, &format!("/api/1/store/roots/{name}"))
+            .await?;
+        Ok(())
+    }
+
+    /// Streams a file from the store.
+    pub async fn download(
+        &self,
+        hash: &SupportedHash,
+    ) -> Result<impl Stream<Item = Result<Bytes, ClientError>> + Send + Unpin, ClientError> {
+        self.stream(&format!("/api/1/download/{hash}")).await
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::PathBuf,
+};
+
+use nck_hashing::SupportedHash;
+use serde::{Deserialize, Serialize};
+
+/// The progress of a build.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BuildStatus {
+    Queued,
+    Running,
+    Succeeded {
+        outputs: BTreeMap<String, BuildOutput>,
+    },
+    Failed {
+        /// The index of the action that failed.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        action: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        exit_code: Option<i32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signal: Option<i32>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    },
+    Cancelled,
+}
+
+impl BuildStatus {
+    /// Whether the build has stopped, successfully or otherwise.
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, Self::Queued | Self::Running)
+    }
+}
+
+/// An output of a successful build.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildOutput {
+    /// The read-only directory containing the output.
+    pub path: PathBuf,
+    /// The archive, under `files/`, that lists the entries of the output.
+    pub manifest: SupportedHash,
+}
+
+/// An output of another build.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct OutputReference {
+    /// The hash of the spec that produced the output.
+    pub build: SupportedHash,
+    pub output: String,
+}
+
+/// A build, along with its status.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildRecord {
+    pub name: String,
+    /// The files that the build depends on.
+    pub files: BTreeSet<SupportedHash>,
+    /// The outputs of other builds that the build depends on.
+    pub dependencies: BTreeSet<OutputReference>,
+    /// When the build started, in seconds since the Unix epoch.
+    pub started: u64,
+    /// When the build finished, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub finished: Option<u64>,
+    #[serde(flatten)]
+    pub status: BuildStatus,
+}
+
+/// What is known about a file in the store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRecord {
+    pub size: u64,
+    /// When the file was added to the store, in seconds since the Unix epoch.
+    pub added: u64,
+    /// When the file was last downloaded or used by a build, in seconds since the Unix epoch.
+    pub last_used: u64,
+}
+
+/// A build that is accepting files.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingBuild {
+    pub name: String,
+    /// The files that are locked by the build.
+    pub files: Vec<SupportedHash>,
+}
+
+/// Where a file from a multipart upload was stored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadedFile {
+    pub hash: SupportedHash,
+    pub location: String,
+}
+
+/// The outcome of a garbage collection pass.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GcReport {
+    /// Whether anything was actually removed.
+    pub dry_run: bool,
+    /// Files that were removed from `files/`.
+    pub files: Vec<SupportedHash>,
+    /// Entries that were removed from `temp/`.
+    pub temp: Vec<String>,
+    pub reclaimed_bytes: u64,
+}
+
+/// A problem found while verifying the store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum VerifyIssue {
+    /// The contents of a file do not match its name.
+    Mismatch {
+        expected: SupportedHash,
+        actual: SupportedHash,
+    },
+    /// A file is shorter than the size that was recorded when it was added.
+    Truncated {
+        expected: SupportedHash,
+        size: u64,
+        recorded: u64,
+    },
+    /// A file could not be read.
+    Unreadable {
+        expected: SupportedHash,
+        error: String,
+    },
+    /// An entry that is not named after a hash, or a temporary entry that nothing is using.
+    Stray { path: PathBuf },
+}
+
+/// The outcome of verifying the store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyReport {
+    /// Whether the affected entries were moved to `quarantine/`.
+    pub quarantined: bool,
+    /// The number of files that were rehashed.
+    pub checked: usize,
+    pub issues: Vec<VerifyIssue>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct RootTarget {
+    pub hash: SupportedHash,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct FetchRequest<'a> {
+    pub url: &'a str,
+    pub hash: SupportedHash,
+}
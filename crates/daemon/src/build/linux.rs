@@ -8,7 +8,10 @@ mod shiftfs;
 mod user_ns;
 
 use crate::settings::Settings;
-pub use process::main_process::{Controller, PendingController, Sandbox};
+pub use process::{
+    main_process::{Controller, PendingController, Sandbox},
+    ActionStatus, SandboxAction, SandboxConfig, SandboxResponse, SupervisorId,
+};
 
 pub fn create_controller(config: Settings) -> anyhow::Result<PendingController> {
     process::main_process::main_process(config.daemon)
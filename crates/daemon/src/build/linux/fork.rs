@@ -363,8 +363,8 @@ mod test {
                 // Inside the forked process. We call `container_clone` and pass
                 // the pid to the parent process.
                 let pid = clone(Box::new(|| 0), CloneFlags::CLONE_PARENT)?;
-                let mut pid = pid.as_raw().to_ne_bytes();
-                child_socket.write_all(&mut pid)?;
+                let pid = pid.as_raw().to_ne_bytes();
+                child_socket.write_all(&pid)?;
                 std::process::exit(0);
             }
         };
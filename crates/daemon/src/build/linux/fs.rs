@@ -9,11 +9,14 @@ use std::{
 use nix::{
     fcntl::{open, OFlag},
     libc::{
-        syscall, AT_EMPTY_PATH, AT_RECURSIVE, EBADF, MOVE_MOUNT_F_EMPTY_PATH, OPEN_TREE_CLOEXEC,
+        AT_EMPTY_PATH, AT_RECURSIVE, EBADF, MOVE_MOUNT_F_EMPTY_PATH, OPEN_TREE_CLOEXEC,
         OPEN_TREE_CLONE,
     },
     mount::{MntFlags, MsFlags},
-    sys::stat::{makedev, Mode, SFlag},
+    sys::{
+        stat::{makedev, Mode, SFlag},
+        statvfs::{statvfs, FsFlags},
+    },
     unistd::fchdir,
     NixPath,
 };
@@ -209,6 +212,40 @@ pub fn bind(
     }
 }
 
+#[tracing::instrument(level = "trace", skip_all)]
+pub fn bind_read_only(src: impl AsRef<Path>, dest: impl AsRef<Path>) -> nix::Result<()> {
+    let dest = dest.as_ref();
+    bind(src, dest, None, None)?;
+
+    // The read-only flag is ignored when creating a bind mount, so it has to be applied with a remount. Any flags that
+    // were locked by the user namespace must be preserved, otherwise the remount fails.
+    tracing::trace!(?dest, "remounting read-only");
+    let existing = statvfs(dest)?.flags();
+    let mut flags = MsFlags::MS_REMOUNT | MsFlags::MS_BIND | MsFlags::MS_RDONLY;
+    for (existing_flag, flag) in [
+        (FsFlags::ST_NOSUID, MsFlags::MS_NOSUID),
+        (FsFlags::ST_NODEV, MsFlags::MS_NODEV),
+        (FsFlags::ST_NOEXEC, MsFlags::MS_NOEXEC),
+        (FsFlags::ST_NOATIME, MsFlags::MS_NOATIME),
+        (FsFlags::ST_NODIRATIME, MsFlags::MS_NODIRATIME),
+        (FsFlags::ST_RELATIME, MsFlags::MS_RELATIME),
+    ] {
+        if existing.contains(existing_flag) {
+            flags |= flag;
+        }
+    }
+
+    mount(SYS_NONE, dest, SYS_NONE, flags, SYS_NONE)
+}
+
+/// Attaches a detached mount, such as one created by [`nck_io::fs::clone_mount`], at the destination.
+#[tracing::instrument(level = "trace", skip_all)]
+pub fn attach(tree: &impl AsRawFd, dest: impl AsRef<Path>) -> nix::Result<()> {
+    let dest = dest.as_ref();
+    tracing::trace!(?dest, "attaching mount");
+    move_mount(Some(tree.as_raw_fd()), "", None, dest)
+}
+
 #[tracing::instrument(level = "trace", skip_all, fields(new_root = ?new_root.as_ref()))]
 pub fn pivot(new_root: impl AsRef<Path>) -> nix::Result<()> {
     let new_root = new_root.as_ref();
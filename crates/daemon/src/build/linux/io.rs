@@ -115,6 +115,8 @@ impl AsyncMessageChannel for AsyncFd<UnixStream> {
                 let mut socket = socket.readable().await?;
                 let mut fds_buf = [0; 16];
                 match socket.try_io(|fd| fd.get_ref().recv_fds(message, &mut fds_buf)) {
+                    // The peer has closed its end, so the rest of the message will never arrive.
+                    Ok(Ok((0, _))) => return Err(ErrorKind::UnexpectedEof.into()),
                     Ok(Ok((size, fd_size))) => {
                         message = &mut message[size..];
                         fds.extend(
@@ -201,6 +203,9 @@ impl MessageChannel for UnixStream {
             while !message.is_empty() {
                 let mut fds_buf = [0; 16];
                 let (size, fd_size) = socket.recv_fds(message, &mut fds_buf)?;
+                if size == 0 {
+                    return Err(ErrorKind::UnexpectedEof.into());
+                }
                 message = &mut message[size..];
                 fds.extend(
                     fds_buf[..fd_size]
@@ -235,6 +240,7 @@ impl ChannelError for Error {
                 | ErrorKind::ConnectionAborted
                 | ErrorKind::NotConnected
                 | ErrorKind::BrokenPipe
+                | ErrorKind::UnexpectedEof
         )
     }
 }
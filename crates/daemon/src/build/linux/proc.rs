@@ -64,13 +64,26 @@ impl ChildProcess {
         self.0.borrow_mut().take()
     }
 
+    /// Checks whether the child process has exited, reaping it if so.
+    pub fn exited(&self) -> bool {
+        let Some(pid) = *self.0.borrow() else {
+            return true;
+        };
+        if Self::poll(pid).is_ok() {
+            self.take();
+            true
+        } else {
+            false
+        }
+    }
+
     fn poll(pid: Pid) -> std::io::Result<()> {
         match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
             Ok(v) => match v {
                 nix::sys::wait::WaitStatus::Exited(_, _) => Ok(()),
                 nix::sys::wait::WaitStatus::Signaled(_, _, _) => Ok(()),
-                nix::sys::wait::WaitStatus::Stopped(_, _) => Ok(()),
-                nix::sys::wait::WaitStatus::PtraceEvent(_, _, _)
+                nix::sys::wait::WaitStatus::Stopped(_, _)
+                | nix::sys::wait::WaitStatus::PtraceEvent(_, _, _)
                 | nix::sys::wait::WaitStatus::Continued(_)
                 | nix::sys::wait::WaitStatus::StillAlive
                 | nix::sys::wait::WaitStatus::PtraceSyscall(_) => {
@@ -9,8 +9,21 @@ mod sandbox_process;
 mod supervisor_process;
 mod zygote_process;
 
+pub use sandbox_process::{ActionStatus, SandboxAction, SandboxResponse};
+pub use zygote_process::SupervisorId;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SandboxConfig {
     working: PathBuf,
     namespace: UserNamespaceConfig,
 }
+
+impl SandboxConfig {
+    /// Creates a config for a sandbox, whose user namespace is filled in by the controller.
+    pub fn new(working: PathBuf) -> Self {
+        Self {
+            working,
+            namespace: UserNamespaceConfig::default(),
+        }
+    }
+}
@@ -1,25 +1,47 @@
 use std::{
-    os::{fd::OwnedFd, unix::net::UnixStream},
+    os::{
+        fd::{FromRawFd as _, OwnedFd},
+        unix::net::UnixStream,
+    },
+    path::PathBuf,
     sync::Arc,
 };
 
-use nix::{sched::CloneFlags, unistd::getgid};
+use nix::{
+    fcntl::OFlag,
+    sched::CloneFlags,
+    unistd::{getgid, pipe2, Gid, Uid},
+};
 use thiserror::Error;
-use tokio::{io::unix::AsyncFd, sync::Mutex};
+use tokio::{io::unix::AsyncFd, net::unix::pipe, sync::Mutex};
 
 use crate::{
     build::linux::{
         fork,
-        io::AsyncMessageChannel as _,
+        io::{AsyncMessageChannel as _, EmptyFds},
         proc::ChildProcess,
-        user_ns::{LinuxIdMapping, UserNamespaceError},
+        user_ns::{LinuxIdMapping, UserNamespaceConfig},
     },
     settings::DaemonSettings,
 };
 
-use super::{zygote_process::InitialRequest, SandboxConfig};
+use super::{
+    sandbox_process::{SandboxAction, SandboxRequest, SandboxResponse},
+    zygote_process::{InitialRequest, SupervisorId},
+    SandboxConfig,
+};
 
 pub fn main_process(daemon_settings: DaemonSettings) -> anyhow::Result<PendingController> {
+    // Looked up once, so that a missing newuidmap is reported at startup rather than by every build.
+    let mut namespace = UserNamespaceConfig::new()?;
+    let (uid, gid) = sandbox_owner(&daemon_settings);
+    namespace
+        .uid_mappings_mut()
+        .push(LinuxIdMapping::new(0, uid.as_raw(), 1));
+    namespace
+        .gid_mappings_mut()
+        .push(LinuxIdMapping::new(0, gid.as_raw(), 1));
+
     let (parent, child) = UnixStream::pair()?;
     let cb = Box::new(move || match child.try_clone() {
         Ok(child) => super::zygote_process::zygote_process(child),
@@ -30,11 +52,17 @@ pub fn main_process(daemon_settings: DaemonSettings) -> anyhow::Result<PendingCo
 
     Ok(PendingController {
         daemon_settings,
+        namespace,
         _zygote: zygote,
         socket: parent,
     })
 }
 
+/// The host user and group that the root user of a sandbox is mapped to.
+fn sandbox_owner(daemon_settings: &DaemonSettings) -> (Uid, Gid) {
+    (Uid::from_raw(daemon_settings.linux.sub_uid.min), getgid())
+}
+
 /// A controller that has not been activated.
 ///
 /// This primarily exists to avoid interacting with tokio prior to creating the zygote. Forking/cloning with threads
@@ -44,24 +72,29 @@ pub struct PendingController {
     _zygote: ChildProcess,
     socket: UnixStream,
     daemon_settings: DaemonSettings,
+    namespace: UserNamespaceConfig,
 }
 
 impl PendingController {
     pub async fn into_controller(self) -> anyhow::Result<Controller> {
         self.socket.set_nonblocking(true)?;
         Ok(Controller(Arc::new(Mutex::new(ControllerState {
-            _zygote: self._zygote,
+            _zygote: Some(self._zygote),
             socket: AsyncFd::new(self.socket)?,
             daemon_settings: self.daemon_settings,
+            namespace: self.namespace,
         }))))
     }
 }
 
 #[derive(Debug)]
 struct ControllerState {
-    _zygote: ChildProcess,
+    /// `None` when tests stand in for the zygote.
+    _zygote: Option<ChildProcess>,
     socket: AsyncFd<UnixStream>,
     daemon_settings: DaemonSettings,
+    /// The user namespace of every sandbox.
+    namespace: UserNamespaceConfig,
 }
 
 #[derive(Debug)]
@@ -71,8 +104,6 @@ pub struct Controller(Arc<Mutex<ControllerState>>);
 pub enum SpawnError {
     #[error(transparent)]
     IO(#[from] std::io::Error),
-    #[error(transparent)]
-    UserNamespace(#[from] UserNamespaceError),
     #[error("spawn failed in child process")]
     SpawnFailed(super::zygote_process::SpawnError),
 }
@@ -80,19 +111,7 @@ pub enum SpawnError {
 impl Controller {
     pub async fn spawn_async(&self, mut config: SandboxConfig) -> Result<Sandbox, SpawnError> {
         let s = self.0.clone().lock_owned().await;
-
-        config
-            .namespace
-            .uid_mappings_mut()
-            .push(LinuxIdMapping::new(
-                0,
-                s.daemon_settings.linux.sub_uid.min,
-                1,
-            ));
-        config
-            .namespace
-            .gid_mappings_mut()
-            .push(LinuxIdMapping::new(0, getgid().as_raw(), 1));
+        config.namespace = s.namespace.clone();
 
         let (sandbox_peer, local_sandbox_peer) = UnixStream::pair()?;
         let sandbox_peer = Some(OwnedFd::from(sandbox_peer));
@@ -102,14 +121,123 @@ impl Controller {
         s.socket
             .write_message(InitialRequest::Spawn { config }, sandbox_peer.iter())
             .await?;
+        let supervisor = s
+            .socket
+            .read_message::<Result<SupervisorId, super::zygote_process::SpawnError>, _>(
+                &mut EmptyFds,
+            )
+            .await?
+            .map_err(SpawnError::SpawnFailed)?;
 
         Ok(Sandbox {
             channel: local_sandbox_peer,
+            supervisor,
         })
     }
+
+    /// The host user and group that the root user of a sandbox is mapped to, and so the owner of anything it
+    /// creates.
+    pub async fn sandbox_owner(&self) -> (Uid, Gid) {
+        sandbox_owner(&self.0.lock().await.daemon_settings)
+    }
+
+    /// Stops the supervisor of a sandbox, which tears down the sandbox.
+    ///
+    /// Returns whether the supervisor was still running.
+    pub async fn cancel(&self, supervisor: SupervisorId) -> std::io::Result<bool> {
+        let s = self.0.clone().lock_owned().await;
+        s.socket
+            .write_message(InitialRequest::Cancel { supervisor }, EmptyFds)
+            .await?;
+        s.socket.read_message(&mut EmptyFds).await
+    }
 }
 
 #[derive(Debug)]
 pub struct Sandbox {
     channel: AsyncFd<UnixStream>,
+    supervisor: SupervisorId,
+}
+
+impl Sandbox {
+    pub fn supervisor(&self) -> SupervisorId {
+        self.supervisor
+    }
+
+    /// Starts executing the actions within the sandbox, with the store files bound read-only and the output
+    /// directories bound writable.
+    ///
+    /// The returned pipe receives the output of the commands.
+    pub async fn run(
+        &self,
+        files: Vec<PathBuf>,
+        outputs: Vec<PathBuf>,
+        actions: Vec<SandboxAction>,
+    ) -> std::io::Result<pipe::Receiver> {
+        let (log, sandbox_log) = pipe2(OFlag::O_CLOEXEC)?;
+        let log = unsafe { OwnedFd::from_raw_fd(log) };
+        let sandbox_log = unsafe { OwnedFd::from_raw_fd(sandbox_log) };
+
+        self.channel
+            .write_message(
+                SandboxRequest::Run {
+                    files,
+                    outputs,
+                    actions,
+                },
+                [&sandbox_log],
+            )
+            .await?;
+        pipe::Receiver::from_owned_fd(log)
+    }
+
+    /// Waits for the next response from the sandbox.
+    pub async fn next_response(&self) -> std::io::Result<SandboxResponse> {
+        self.channel.read_message(&mut EmptyFds).await
+    }
+}
+
+#[cfg(test)]
+impl Controller {
+    /// A controller that hands out sandboxes which never respond, for testing the store without starting processes.
+    ///
+    /// Sandboxes are mapped to the current user, so that the store can give them files without privileges.
+    pub fn fake() -> Self {
+        Self::fake_with(None)
+    }
+
+    /// A fake controller whose cancelled sandboxes keep their channels open until `held` is released.
+    pub fn fake_holding() -> (Self, super::zygote_process::HeldSandboxes) {
+        let held = super::zygote_process::HeldSandboxes::default();
+        (Self::fake_with(Some(held.clone())), held)
+    }
+
+    fn fake_with(held: Option<super::zygote_process::HeldSandboxes>) -> Self {
+        use crate::settings::{LinuxSandboxSettings, LinuxSubIdSetting};
+
+        let (local, zygote) = UnixStream::pair().unwrap();
+        for socket in [&local, &zygote] {
+            socket.set_nonblocking(true).unwrap();
+        }
+        tokio::spawn(super::zygote_process::fake_zygote(
+            AsyncFd::new(zygote).unwrap(),
+            held,
+        ));
+
+        let uid = nix::unistd::getuid().as_raw();
+        let gid = getgid().as_raw();
+        Controller(Arc::new(Mutex::new(ControllerState {
+            _zygote: None,
+            socket: AsyncFd::new(local).unwrap(),
+            daemon_settings: DaemonSettings {
+                linux: LinuxSandboxSettings {
+                    sub_uid: LinuxSubIdSetting { min: uid, max: uid },
+                    sub_gid: LinuxSubIdSetting { min: gid, max: gid },
+                },
+                tcp: Default::default(),
+                socket_path: PathBuf::new(),
+            },
+            namespace: UserNamespaceConfig::default(),
+        })))
+    }
 }
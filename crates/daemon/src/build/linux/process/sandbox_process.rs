@@ -1,13 +1,314 @@
-use std::os::{fd::OwnedFd, unix::net::UnixStream};
+use std::{
+    collections::BTreeMap,
+    fs::Permissions,
+    os::{
+        fd::OwnedFd,
+        unix::{fs::PermissionsExt as _, net::UnixStream, process::ExitStatusExt as _},
+    },
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
 
+use anyhow::Context;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    build::linux::{
+        fs::attach,
+        io::{EmptyFds, MessageChannel},
+        rootfs::RootFs,
+    },
+    spec::Action,
+};
+
+/// An action, as executed by the sandbox.
+///
+/// This mirrors [`Action`], which can't be sent over the channel directly because it is internally tagged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SandboxAction {
+    Set {
+        name: String,
+        value: String,
+    },
+    WorkDir {
+        path: PathBuf,
+    },
+    Link {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+        executable: bool,
+    },
+    Exec {
+        path: PathBuf,
+        args: Vec<String>,
+    },
+}
+
+impl From<&Action> for SandboxAction {
+    fn from(value: &Action) -> Self {
+        match value.clone() {
+            Action::Set { name, value } => Self::Set { name, value },
+            Action::WorkDir { path } => Self::WorkDir { path },
+            // Validated specs never have executable links.
+            Action::Link { from, to, .. } => Self::Link { from, to },
+            Action::Copy {
+                from,
+                to,
+                executable,
+            } => Self::Copy {
+                from,
+                to,
+                executable,
+            },
+            Action::Exec { path, args } => Self::Exec { path, args },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum SandboxRequest {
+    /// Applies the actions in order, stopping at the first one that does not succeed.
+    ///
+    /// The write end of a pipe, which receives the output of commands, is sent alongside the request.
+    Run {
+        /// Store files to bind, read-only, at the same location within the sandbox.
+        files: Vec<PathBuf>,
+        /// Output directories to bind, writable, at the same location within the sandbox.
+        outputs: Vec<PathBuf>,
+        actions: Vec<SandboxAction>,
+    },
+}
+
+/// The outcome of a single action.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActionStatus {
+    Success,
+    /// The command exited with a non-zero code.
+    Exited(i32),
+    /// The command was killed by a signal.
+    Signaled(i32),
+    /// The action could not be applied.
+    Failed(String),
+}
+
+impl ActionStatus {
+    pub fn is_success(&self) -> bool {
+        matches!(self, ActionStatus::Success)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
-pub enum SandboxRequest {}
+pub enum SandboxResponse {
+    /// The root filesystem could not be prepared, so no actions were executed.
+    SetupFailed(String),
+    /// An action has completed.
+    Action { index: usize, status: ActionStatus },
+    /// No further actions will be executed.
+    Finished,
+}
+
+#[derive(Debug)]
+struct ActionState {
+    env: BTreeMap<String, String>,
+    /// Receives the output of commands, which is otherwise inherited.
+    log: Option<OwnedFd>,
+}
+
+impl ActionState {
+    fn apply(&mut self, action: SandboxAction) -> ActionStatus {
+        match self.try_apply(action) {
+            Ok(status) => status,
+            Err(error) => {
+                tracing::debug!(?error, "action failed");
+                ActionStatus::Failed(format!("{error:#}"))
+            }
+        }
+    }
+
+    fn try_apply(&mut self, action: SandboxAction) -> anyhow::Result<ActionStatus> {
+        match action {
+            SandboxAction::Set { name, value } => {
+                tracing::trace!(name, value, "setting environment variable");
+                self.env.insert(name, value);
+            }
+            SandboxAction::WorkDir { path } => {
+                tracing::trace!(?path, "changing working directory");
+                std::env::set_current_dir(&path)
+                    .with_context(|| format!("changing directory to {path:?}"))?;
+            }
+            SandboxAction::Link { from, to } => {
+                tracing::trace!(?from, ?to, "linking file");
+                create_parent(&to)?;
+                std::os::unix::fs::symlink(&from, &to)
+                    .with_context(|| format!("linking {from:?} to {to:?}"))?;
+            }
+            SandboxAction::Copy {
+                from,
+                to,
+                executable,
+            } => copy(&from, &to, if executable { 0o755 } else { 0o644 })?,
+            SandboxAction::Exec { path, args } => {
+                tracing::trace!(?path, ?args, "executing command");
+                let mut command = Command::new(&path);
+                command.args(args).env_clear().envs(self.env.iter());
+                if let Some(log) = &self.log {
+                    command
+                        .stdin(Stdio::null())
+                        .stdout(log.try_clone()?)
+                        .stderr(log.try_clone()?);
+                }
+                let status = command
+                    .status()
+                    .with_context(|| format!("executing {path:?}"))?;
+
+                return Ok(match (status.code(), status.signal()) {
+                    (Some(0), _) => ActionStatus::Success,
+                    (Some(code), _) => ActionStatus::Exited(code),
+                    (None, Some(signal)) => ActionStatus::Signaled(signal),
+                    (None, None) => ActionStatus::Failed(format!("unknown exit status {status}")),
+                });
+            }
+        }
+        Ok(ActionStatus::Success)
+    }
+}
+
+fn create_parent(path: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| format!("creating {parent:?}"))?;
+    }
+    Ok(())
+}
+
+fn copy(from: &Path, to: &Path, mode: u32) -> anyhow::Result<()> {
+    tracing::trace!(?from, ?to, mode, "copying file");
+    create_parent(to)?;
+    std::fs::copy(from, to).with_context(|| format!("copying {from:?} to {to:?}"))?;
+    std::fs::set_permissions(to, Permissions::from_mode(mode))
+        .with_context(|| format!("setting the mode of {to:?}"))?;
+    Ok(())
+}
 
 #[tracing::instrument(level = "trace", skip_all)]
 pub fn sandbox_process(sandbox_peer: OwnedFd, tmpfs: OwnedFd) -> anyhow::Result<()> {
+    if let Err(error) = prctl::set_name("nck-sandbox") {
+        tracing::warn!(?error, "failed to set sandbox name");
+    }
+
     let sandbox_peer: UnixStream = sandbox_peer.into();
 
+    let mut fds = Vec::new();
+    match sandbox_peer.read_message(&mut fds)? {
+        SandboxRequest::Run {
+            files,
+            outputs,
+            actions,
+        } => {
+            let log = fds.pop().context("missing log fd")?;
+            if let Err(error) = setup(&tmpfs, &files, &outputs) {
+                tracing::error!(?error, "failed to prepare the sandbox");
+                sandbox_peer
+                    .write_message(SandboxResponse::SetupFailed(format!("{error:#}")), EmptyFds)?;
+                return Err(error);
+            }
+            run(&sandbox_peer, log, actions)
+        }
+    }
+}
+
+/// Builds the root filesystem within the tmpfs and pivots into it.
+fn setup(tmpfs: &OwnedFd, files: &[PathBuf], outputs: &[PathBuf]) -> anyhow::Result<()> {
+    attach(tmpfs, "/tmp").context("when attaching the tmpfs")?;
+
+    let root = RootFs::new(PathBuf::from("/tmp/root"));
+    root.install()
+        .context("when installing the root filesystem")?;
+    for file in files {
+        root.bind_read_only(file)
+            .with_context(|| format!("when binding {file:?}"))?;
+    }
+    for output in outputs {
+        root.bind(output)
+            .with_context(|| format!("when binding {output:?}"))?;
+    }
+
+    root.pivot().context("when pivoting to the root filesystem")
+}
+
+fn run(sandbox_peer: &UnixStream, log: OwnedFd, actions: Vec<SandboxAction>) -> anyhow::Result<()> {
+    let mut state = ActionState {
+        env: BTreeMap::new(),
+        log: Some(log),
+    };
+
+    for (index, action) in actions.into_iter().enumerate() {
+        let status = state.apply(action);
+        let success = status.is_success();
+        sandbox_peer.write_message(SandboxResponse::Action { index, status }, EmptyFds)?;
+        if !success {
+            break;
+        }
+    }
+
+    sandbox_peer.write_message(SandboxResponse::Finished, EmptyFds)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, os::unix::fs::PermissionsExt as _};
+
+    use nck_io::fs::TempDir;
+
+    use super::{ActionState, ActionStatus, SandboxAction};
+
+    fn state() -> ActionState {
+        ActionState {
+            env: BTreeMap::new(),
+            log: None,
+        }
+    }
+
+    #[test]
+    fn copy_sets_mode() {
+        let dir = TempDir::new().unwrap();
+        let from = dir.as_path().join("from");
+        let to = dir.as_path().join("nested/to");
+        std::fs::write(&from, "contents").unwrap();
+
+        let status = state().apply(SandboxAction::Copy {
+            from,
+            to: to.clone(),
+            executable: true,
+        });
+        assert_eq!(ActionStatus::Success, status);
+        assert_eq!("contents", std::fs::read_to_string(&to).unwrap());
+        let mode = std::fs::metadata(&to).unwrap().permissions().mode();
+        assert_eq!(0o755, mode & 0o777);
+    }
+
+    #[test]
+    fn exec_reports_status() {
+        let mut state = state();
+        state.apply(SandboxAction::Set {
+            name: "CODE".into(),
+            value: "3".into(),
+        });
+
+        let status = state.apply(SandboxAction::Exec {
+            path: "/bin/sh".into(),
+            args: vec!["-c".into(), "exit $CODE".into()],
+        });
+        assert_eq!(ActionStatus::Exited(3), status);
+
+        let status = state.apply(SandboxAction::Exec {
+            path: "/nonexistent".into(),
+            args: Vec::new(),
+        });
+        assert!(matches!(status, ActionStatus::Failed(_)));
+    }
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     os::{fd::OwnedFd, unix::net::UnixStream},
 };
 
@@ -19,9 +19,16 @@ use crate::build::linux::{
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum InitialRequest {
+    /// Spawns a supervisor, responding with `Result<SupervisorId, SpawnError>`.
     Spawn { config: super::SandboxConfig },
+    /// Stops a supervisor, and therefore its sandbox, responding with whether it was still running.
+    Cancel { supervisor: SupervisorId },
 }
 
+/// Identifies a supervisor process that was started by the zygote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SupervisorId(i32);
+
 #[derive(Debug, Serialize, Deserialize, Error)]
 #[error("failed to spawn the supervisor process")]
 pub struct SpawnError;
@@ -29,29 +36,46 @@ pub struct SpawnError;
 pub fn zygote_process(peer: UnixStream) -> anyhow::Result<()> {
     tracing::info!("zygote started");
     let mut fds = VecDeque::new();
+    let mut supervisors = HashMap::new();
     loop {
         let message = match peer.read_message(&mut fds) {
             Err(e) if e.is_closed_channel() => break,
             other => other?,
         };
 
-        let response = match message {
-            InitialRequest::Spawn { config } => match spawn(config, fds.pop_front()) {
-                Ok(_) => Ok(()),
-                Err(error) => {
-                    tracing::error!(?error, "failed to spawn the supervisor process");
-                    Err(SpawnError)
-                }
-            },
-        };
+        supervisors.retain(|_, supervisor: &mut ChildProcess| !supervisor.exited());
 
-        fds.clear();
-        peer.write_message(response, EmptyFds)?;
+        match message {
+            InitialRequest::Spawn { config } => {
+                let response = match spawn(config, fds.pop_front()) {
+                    Ok(supervisor) => {
+                        let id = SupervisorId(supervisor.inner().as_raw());
+                        supervisors.insert(id, supervisor);
+                        Ok(id)
+                    }
+                    Err(error) => {
+                        tracing::error!(?error, "failed to spawn the supervisor process");
+                        Err(SpawnError)
+                    }
+                };
+                fds.clear();
+                peer.write_message(response, EmptyFds)?;
+            }
+            InitialRequest::Cancel { supervisor } => {
+                // Dropping the supervisor terminates it, escalating to SIGKILL if it does not exit in time.
+                let running = supervisors.remove(&supervisor).is_some();
+                tracing::info!(?supervisor, running, "cancelled supervisor process");
+                peer.write_message(running, EmptyFds)?;
+            }
+        }
     }
     Ok(())
 }
 
-fn spawn(config: super::SandboxConfig, mut sandbox_peer: Option<OwnedFd>) -> anyhow::Result<()> {
+fn spawn(
+    config: super::SandboxConfig,
+    mut sandbox_peer: Option<OwnedFd>,
+) -> anyhow::Result<ChildProcess> {
     let sandbox_peer = sandbox_peer.take().ok_or_else(|| anyhow!("missing fd"))?;
     let (supervisor_peer, local_supervisor_peer) = UnixStream::pair()?;
 
@@ -80,11 +104,64 @@ fn spawn(config: super::SandboxConfig, mut sandbox_peer: Option<OwnedFd>) -> any
     local_supervisor_peer.write_message(SupervisorMapped::Proceed, EmptyFds)?;
     tracing::trace!("waiting for temp request");
 
-    if let Err::<(), SupervisorError>(_) = local_supervisor_peer.read_message(&mut EmptyFds)? {
+    if local_supervisor_peer
+        .read_message::<Result<(), SupervisorError>, _>(&mut EmptyFds)?
+        .is_err()
+    {
         bail!("supervisor process failed");
     }
-    pid.forget();
     tracing::info!("started supervisor process");
 
+    Ok(pid)
+}
+
+/// Stands in for the zygote, acknowledging requests without starting any processes.
+///
+/// The channel of each sandbox is held open until its supervisor is cancelled, so a sandbox never responds, and loses
+/// contact with the store once it has been cancelled, or once `held` is released if it is given.
+#[cfg(test)]
+pub async fn fake_zygote(
+    peer: tokio::io::unix::AsyncFd<UnixStream>,
+    held: Option<HeldSandboxes>,
+) -> std::io::Result<()> {
+    use crate::build::linux::io::AsyncMessageChannel as _;
+
+    let mut sandboxes = HashMap::new();
+    for id in 1.. {
+        let mut fds = Vec::new();
+        // The store has been dropped.
+        let Ok(message) = peer.read_message(&mut fds).await else {
+            break;
+        };
+        match message {
+            InitialRequest::Spawn { .. } => {
+                let supervisor = SupervisorId(id);
+                sandboxes.insert(supervisor, fds);
+                peer.write_message(Ok::<_, SpawnError>(supervisor), EmptyFds)
+                    .await?;
+            }
+            InitialRequest::Cancel { supervisor } => {
+                let fds = sandboxes.remove(&supervisor);
+                let running = fds.is_some();
+                if let (Some(held), Some(fds)) = (&held, fds) {
+                    held.0.lock().unwrap().push(fds);
+                }
+                peer.write_message(running, EmptyFds).await?;
+            }
+        }
+    }
     Ok(())
 }
+
+/// The channels of sandboxes that a fake zygote has cancelled, which stay open until they are released.
+#[cfg(test)]
+#[derive(Debug, Clone, Default)]
+pub struct HeldSandboxes(std::sync::Arc<std::sync::Mutex<Vec<Vec<OwnedFd>>>>);
+
+#[cfg(test)]
+impl HeldSandboxes {
+    /// Closes the channels, so that the store loses contact with the sandboxes.
+    pub fn release(&self) {
+        self.0.lock().unwrap().clear();
+    }
+}
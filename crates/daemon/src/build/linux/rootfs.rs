@@ -2,18 +2,20 @@ use std::{
     fs::{OpenOptions, Permissions},
     io::Write,
     os::unix::fs::{symlink, PermissionsExt as _},
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
+use anyhow::bail;
+
 use nix::{
     mount::MsFlags,
     unistd::{Gid, Uid},
 };
 
-use super::fs::{bind, mount, MountType, SYS_NONE};
+use super::fs::{bind, bind_read_only, mount, pivot, MountType, SYS_NONE};
 
 #[derive(Debug)]
-struct RootFs {
+pub struct RootFs {
     root: PathBuf,
     etc: PathBuf,
     dev: PathBuf,
@@ -23,7 +25,7 @@ struct RootFs {
 }
 
 impl RootFs {
-    fn new(root: PathBuf) -> Self {
+    pub fn new(root: PathBuf) -> Self {
         Self {
             etc: root.join("etc"),
             tmp: root.join("tmp"),
@@ -45,7 +47,6 @@ impl RootFs {
         let mut w = OpenOptions::new()
             .create(true)
             .append(true)
-            .write(true)
             .open(etc_group)?;
         write!(w, "{name}:!:{gid}:")?;
         let mut first = true;
@@ -74,9 +75,8 @@ impl RootFs {
         let mut w = OpenOptions::new()
             .create(true)
             .append(true)
-            .write(true)
             .open(etc_passwd)?;
-        write!(w, "{name}:x:{uid}:{gid}:{home}:/sbin/nologin\n")?;
+        writeln!(w, "{name}:x:{uid}:{gid}:{home}:/sbin/nologin")?;
         Ok(())
     }
 
@@ -89,16 +89,20 @@ impl RootFs {
         tracing::trace!("creating /etc");
         std::fs::create_dir_all(self.etc.as_path())?;
 
-        self.add_group::<&str>("root", Gid::from_raw(0), []);
-        self.add_group::<&str>("nogroup", Gid::from_raw(65534), []);
+        self.add_group::<&str>("root", Gid::from_raw(0), [])?;
+        self.add_group::<&str>("nogroup", Gid::from_raw(65534), [])?;
 
-        self.add_user::<&str>("root", Uid::from_raw(0), Gid::from_raw(0), None);
-        self.add_user::<&str>("nobody", Uid::from_raw(65534), Gid::from_raw(65534), None);
+        self.add_user::<&str>("root", Uid::from_raw(0), Gid::from_raw(0), None)?;
+        self.add_user::<&str>("nobody", Uid::from_raw(65534), Gid::from_raw(65534), None)?;
 
         tracing::trace!("creating /etc/hosts");
         let etc_hosts = self.etc.join("hosts");
         std::fs::write(etc_hosts, "127.0.0.1 localhost\n::1 localhost\n")?;
 
+        tracing::trace!("creating /tmp");
+        std::fs::create_dir_all(&self.tmp)?;
+        std::fs::set_permissions(&self.tmp, Permissions::from_mode(0o1777))?;
+
         tracing::trace!("creating /dev/pts");
         let dev_pts = self.dev.join("pts");
         std::fs::create_dir_all(&dev_pts)?;
@@ -176,4 +180,110 @@ impl RootFs {
 
         Ok(())
     }
+
+    /// Binds a host path, read-only, at the same location within the root.
+    #[tracing::instrument(level = "trace", skip_all, fields(path = ?path.as_ref()))]
+    pub fn bind_read_only(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        bind_read_only(path, self.destination(path)?)?;
+        Ok(())
+    }
+
+    /// Binds a host path, writable, at the same location within the root.
+    #[tracing::instrument(level = "trace", skip_all, fields(path = ?path.as_ref()))]
+    pub fn bind(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        bind(path, self.destination(path)?, None, None)?;
+        Ok(())
+    }
+
+    /// Where a host path appears within the root.
+    ///
+    /// Relative paths are rejected rather than resolved, because they usually come from a relative store path in the
+    /// settings, and would be bound somewhere other than where the build expects to find them.
+    fn destination(&self, path: &Path) -> anyhow::Result<PathBuf> {
+        let Ok(relative) = path.strip_prefix("/") else {
+            bail!("cannot bind {path:?} into the sandbox because it is not absolute, is the store path relative?");
+        };
+        if relative.components().any(|c| c == Component::ParentDir) {
+            bail!("cannot bind {path:?} into the sandbox because it contains '..'");
+        }
+        Ok(self.root.join(relative))
+    }
+
+    /// Makes the root the root directory of the current process.
+    pub fn pivot(&self) -> anyhow::Result<()> {
+        pivot(&self.root)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use nck_io::fs::TempDir;
+    use nix::{
+        sched::CloneFlags,
+        sys::wait::{waitpid, WaitStatus},
+        unistd::{getgid, getuid},
+    };
+
+    use super::RootFs;
+    use crate::build::linux::fork::clone;
+
+    #[test]
+    fn destination() {
+        let root = RootFs::new("/tmp/root".into());
+        assert_eq!(
+            Path::new("/tmp/root/var/nck/store/files/a"),
+            root.destination(Path::new("/var/nck/store/files/a"))
+                .unwrap()
+        );
+
+        let error = root
+            .destination(Path::new("store/files/a"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("not absolute"), "{error}");
+        assert!(root.destination(Path::new("/store/../etc")).is_err());
+    }
+
+    /// Binds a file read-only and a directory writable, in a mount namespace so that the host is unaffected.
+    #[test]
+    fn bind_in_namespace() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.as_path().join("file");
+        let output = dir.as_path().join("output");
+        std::fs::write(&file, "contents").unwrap();
+        std::fs::create_dir(&output).unwrap();
+        let root = RootFs::new(dir.as_path().join("root"));
+        let (uid, gid) = (getuid(), getgid());
+
+        let (source, target) = (file.clone(), output.clone());
+        let cb = Box::new(move || -> anyhow::Result<()> {
+            // Maps the current user to root, which is allowed without privileges.
+            std::fs::write("/proc/self/setgroups", "deny")?;
+            std::fs::write("/proc/self/uid_map", format!("0 {uid} 1"))?;
+            std::fs::write("/proc/self/gid_map", format!("0 {gid} 1"))?;
+
+            root.bind_read_only(&source)?;
+            let bound = root.destination(&source)?;
+            anyhow::ensure!(std::fs::read_to_string(&bound)? == "contents");
+            let error = std::fs::write(&bound, "changed").unwrap_err();
+            anyhow::ensure!(error.raw_os_error() == Some(nix::libc::EROFS), "{error}");
+
+            root.bind(&target)?;
+            std::fs::write(root.destination(&target)?.join("built"), "built")?;
+            Ok(())
+        });
+        let pid = clone(cb, CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWNS).unwrap();
+        assert_eq!(WaitStatus::Exited(pid, 0), waitpid(pid, None).unwrap());
+
+        assert_eq!("contents", std::fs::read_to_string(&file).unwrap());
+        assert_eq!(
+            "built",
+            std::fs::read_to_string(output.join("built")).unwrap()
+        );
+    }
 }
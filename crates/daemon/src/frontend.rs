@@ -1,7 +1,10 @@
 use crate::{settings::Settings, store::Store};
 
 mod build;
+mod digest;
+mod download;
 mod serve;
+mod store;
 
 #[derive(Debug, Clone)]
 struct FrontendState {
@@ -9,11 +12,38 @@ struct FrontendState {
 }
 
 pub async fn frontend(store: Store, settings: Settings) -> anyhow::Result<()> {
+    serve::serve(&settings.daemon, create_app(store)).await?;
+
+    Ok(())
+}
+
+fn create_app(store: Store) -> axum::Router {
     let state = FrontendState { store };
 
-    let app = axum::Router::new().nest("/api/1/build", build::create_routes(state.clone()));
+    axum::Router::new()
+        .nest("/api/1/build", build::create_routes(state.clone()))
+        .nest("/api/1/store", store::create_routes(state.clone()))
+        .nest("/api/1/download", download::create_routes(state.clone()))
+}
+
+/// Creates the API, backed by a store in a temporary directory that must outlive it.
+#[cfg(test)]
+async fn test_app() -> (nck_io::fs::TempDir, axum::Router) {
+    let dir = nck_io::fs::TempDir::new().unwrap();
+    let store = Store::for_test(dir.as_path()).await;
+    (dir, create_app(store))
+}
 
-    serve::serve(&settings.daemon, app).await?;
+/// Sends a request to the API, returning the response once its body has been read.
+#[cfg(test)]
+async fn send(
+    app: &axum::Router,
+    request: hyper::Request<axum::body::Body>,
+) -> hyper::Response<bytes::Bytes> {
+    use http_body_util::BodyExt as _;
+    use tower::ServiceExt as _;
 
-    Ok(())
+    let (parts, body) = app.clone().oneshot(request).await.unwrap().into_parts();
+    let body = body.collect().await.unwrap().to_bytes();
+    hyper::Response::from_parts(parts, body)
 }
@@ -1,38 +1,60 @@
-use std::{collections::HashSet, io::ErrorKind, sync::Arc};
+use std::{collections::BTreeMap, io::ErrorKind, sync::Arc};
 
 use axum::{
-    extract::{Path, State},
-    routing::post,
+    extract::{rejection::JsonRejection, DefaultBodyLimit, Multipart, Path, State},
+    routing::{get, post},
     Json, Router,
 };
-use axum_core::{body::Body, extract::Request, response::Response};
-use dashmap::{mapref::entry::Entry, DashMap};
+use axum_core::{
+    body::Body,
+    extract::{FromRequest, Request},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use dashmap::DashMap;
 use derive_more::{Deref, DerefMut};
-use futures::StreamExt;
+use futures::{Stream, StreamExt};
 use hyper::{header, HeaderMap, StatusCode};
-use nck_hashing::{StableHashExt, SupportedHash, SupportedHasher};
+use nck_hashing::{SupportedHash, SupportedHasher};
+use serde::{Deserialize, Serialize};
 use tokio::{
     io::AsyncWriteExt,
     sync::{Mutex, OwnedMappedMutexGuard, OwnedMutexGuard},
 };
 
+use tokio_util::io::StreamReader;
+
 use crate::{
     app_error,
     axum_extensions::{AppError, AppErrorOption, AppErrorReason},
-    store::StoreLock,
+    spec::Spec,
+    store::{FetchError, Store, StoreLock, TempStoreEntry, UnpackError},
 };
 
-use super::FrontendState;
+use super::{digest::expected_hashes, FrontendState};
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct PendingBuildState {
-    locks: HashSet<StoreLock>,
+    entry: TempStoreEntry,
+    locks: BTreeMap<SupportedHash, StoreLock>,
+}
+
+impl PendingBuildState {
+    /// Releases the locked files and removes the working directory.
+    async fn discard(self) -> std::io::Result<()> {
+        drop(self.locks);
+        self.entry.remove().await
+    }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 struct PendingBuild(Arc<Mutex<Option<PendingBuildState>>>);
 
 impl PendingBuild {
+    fn new(state: PendingBuildState) -> Self {
+        Self(Arc::new(Mutex::new(Some(state))))
+    }
+
     async fn lock(
         &self,
     ) -> Option<OwnedMappedMutexGuard<Option<PendingBuildState>, PendingBuildState>> {
@@ -64,8 +86,15 @@ struct BuildsState(Arc<InnerState>);
 
 pub fn create_routes(frontend_state: FrontendState) -> Router {
     Router::new()
-        .route("/", post(create_build))
-        .route("/:name/file", post(post_file))
+        .route("/", get(list_builds).post(create_build))
+        .route("/:name", get(get_build).delete(delete_build))
+        // Files are streamed into the store, so multipart uploads don't need to be limited.
+        .route(
+            "/:name/file",
+            post(post_file).layer(DefaultBodyLimit::disable()),
+        )
+        .route("/:name/fetch", post(fetch_file))
+        .route("/:name/archive", post(post_archive))
         .route("/:name/run", post(run))
         .with_state(BuildsState(Arc::new(InnerState {
             pending_builds: DashMap::new(),
@@ -79,9 +108,17 @@ async fn create_build(State(state): State<BuildsState>) -> Result<Response, AppE
         .create_temporary_entry()
         .await
         .reason("creating working directory")?;
-    let name = store_entry.name();
+    let name = store_entry.name().to_string();
     tracing::debug!(name, "build created");
 
+    state.pending_builds.insert(
+        name.clone(),
+        PendingBuild::new(PendingBuildState {
+            entry: store_entry,
+            locks: BTreeMap::new(),
+        }),
+    );
+
     let response = Response::builder()
         .status(StatusCode::CREATED)
         .header(header::LOCATION, format!("/api/1/build/{name}"))
@@ -90,29 +127,111 @@ async fn create_build(State(state): State<BuildsState>) -> Result<Response, AppE
     Ok(response)
 }
 
+/// A build that is accepting files.
+#[derive(Debug, Serialize)]
+struct PendingBuildInfo {
+    name: String,
+    /// The files that are locked by the build.
+    files: Vec<SupportedHash>,
+}
+
+async fn list_builds(State(state): State<BuildsState>) -> Json<Vec<String>> {
+    let mut names: Vec<_> = state
+        .pending_builds
+        .iter()
+        .map(|entry| entry.key().clone())
+        .collect();
+    names.sort_unstable();
+    Json(names)
+}
+
+async fn get_build(
+    State(state): State<BuildsState>,
+    Path(build_name): Path<String>,
+) -> Result<Json<PendingBuildInfo>, AppError> {
+    let build = state
+        .pending_builds
+        .get(&build_name)
+        .ok_or_else_message(|| format!("build {} not found", build_name))?
+        .clone();
+    let build = build
+        .lock()
+        .await
+        .ok_or_else_message(|| format!("build {} not found", build_name))?;
+
+    Ok(Json(PendingBuildInfo {
+        name: build_name,
+        files: build.locks.keys().copied().collect(),
+    }))
+}
+
+async fn delete_build(
+    State(state): State<BuildsState>,
+    Path(build_name): Path<String>,
+) -> Result<Response, AppError> {
+    let (_, build) = state
+        .pending_builds
+        .remove(&build_name)
+        .ok_or_else_message(|| format!("build {} not found", build_name))?;
+
+    let build = build
+        .take()
+        .await
+        .ok_or_else_message(|| format!("build {} not found", build_name))?;
+    build
+        .discard()
+        .await
+        .reason("removing the working directory")?;
+    tracing::debug!(build_name, "build cancelled");
+
+    let response = Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .reason("creating response")?;
+    Ok(response)
+}
+
 async fn post_file(
     State(state): State<BuildsState>,
     Path(build_name): Path<String>,
     header_map: HeaderMap,
     body: Request,
 ) -> Result<Response, AppError> {
-    // TODO: optionally accept multipart here.
-
-    let mut build = state
+    let build = state
         .pending_builds
         .get(&build_name)
         .ok_or_else_message(|| format!("build {} not found", build_name))?
-        .lock()
-        .await;
+        .clone();
+    let mut build = build.lock().await;
 
     let build = build.as_mut().ok_or_else_message(|| {
         format!("build {} has already been submitted for build", build_name)
     })?;
 
+    let is_multipart = header_map
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("multipart/form-data"));
+    if is_multipart {
+        let multipart = match Multipart::from_request(body, &state).await {
+            Ok(multipart) => multipart,
+            Err(rejection) => {
+                let message = rejection.body_text();
+                Err(rejection)
+                    .reason("parsing the multipart request")
+                    .with_message(|| message)
+                    .status_code(StatusCode::BAD_REQUEST)?
+            }
+        };
+        return post_files(&state.store, build, multipart).await;
+    }
+
+    let expected = expected_hashes(&header_map)?;
+
     if let Some(existing_hash) = header_map.get("If-None-Match") {
         let v = existing_hash.as_bytes();
-        let v = if v.starts_with(b"\"") && v.ends_with(b"\"") {
-            &v[1..(v.len() - 2)]
+        let v = if v.len() >= 2 && v.starts_with(b"\"") && v.ends_with(b"\"") {
+            &v[1..(v.len() - 1)]
         } else {
             app_error!("parsing If-None-Match value")
                 .err()
@@ -125,12 +244,16 @@ async fn post_file(
             .with_message(|| "invalid If-None-Match value".to_string())
             .status_code(StatusCode::BAD_REQUEST)?;
 
-        let hash: SupportedHash = hash.parse().reason("test")?;
+        let hash: SupportedHash = hash
+            .parse()
+            .reason("parsing If-None-Match value")
+            .with_message(|| "invalid If-None-Match value".to_string())
+            .status_code(StatusCode::BAD_REQUEST)?;
 
         match state.frontend_state.store.get_file(&hash).await {
             Ok(file) => {
                 tracing::debug!("file already cached");
-                build.locks.insert(file);
+                build.locks.insert(hash, file);
 
                 let response = Response::builder()
                     .status(StatusCode::SEE_OTHER)
@@ -147,13 +270,136 @@ async fn post_file(
         }
     }
 
-    let mut file = state
-        .frontend_state
-        .store
+    let body = body.into_body().into_data_stream();
+    let (hash, final_lock) = upload(&state.store, body, &expected).await?;
+    build.locks.insert(hash, final_lock);
+
+    tracing::debug!(%hash, "file uploaded");
+
+    let response = Response::builder()
+        .status(StatusCode::CREATED)
+        .header(header::ETAG, format!("\"{hash}\""))
+        .header(header::LOCATION, format!("/api/1/download/{hash}"))
+        .header("X-Nickelpack-Hash", format!("{hash}"))
+        .body(Body::empty())
+        .reason("creating response")?;
+
+    Ok(response)
+}
+
+/// Unpacks an uploaded archive into the store.
+///
+/// The manifest of the archive, and every file it refers to, is locked by the build.
+async fn post_archive(
+    State(state): State<BuildsState>,
+    Path(build_name): Path<String>,
+    body: Request,
+) -> Result<Response, AppError> {
+    let build = state
+        .pending_builds
+        .get(&build_name)
+        .ok_or_else_message(|| format!("build {} not found", build_name))?
+        .clone();
+    let mut build = build.lock().await;
+    let build = build.as_mut().ok_or_else_message(|| {
+        format!("build {} has already been submitted for build", build_name)
+    })?;
+
+    let body = body
+        .into_body()
+        .into_data_stream()
+        .map(|chunk| chunk.map_err(std::io::Error::other));
+    let unpacked = match state.store.unpack(StreamReader::new(body)).await {
+        Ok(unpacked) => unpacked,
+        Err(error) => {
+            let message = error.to_string();
+            let status = match &error {
+                UnpackError::Invalid(_) => StatusCode::BAD_REQUEST,
+                UnpackError::Mismatch { .. } | UnpackError::Missing { .. } => {
+                    StatusCode::UNPROCESSABLE_ENTITY
+                }
+                UnpackError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            Err(error)
+                .reason("unpacking the archive")
+                .with_message(|| message)
+                .status_code(status)?
+        }
+    };
+    let hash = unpacked.manifest;
+    build.locks.extend(unpacked.locks);
+
+    tracing::debug!(%hash, "archive uploaded");
+
+    let response = Response::builder()
+        .status(StatusCode::CREATED)
+        .header(header::ETAG, format!("\"{hash}\""))
+        .header(header::LOCATION, format!("/api/1/download/{hash}"))
+        .header("X-Nickelpack-Hash", format!("{hash}"))
+        .body(Body::empty())
+        .reason("creating response")?;
+    Ok(response)
+}
+
+/// Where a file from a multipart upload was stored.
+#[derive(Debug, Serialize)]
+struct UploadedFile {
+    hash: SupportedHash,
+    location: String,
+}
+
+/// Uploads every part of a multipart request as a separate file.
+///
+/// Each part may have its own expected hash headers.
+async fn post_files(
+    store: &Store,
+    build: &mut PendingBuildState,
+    mut multipart: Multipart,
+) -> Result<Response, AppError> {
+    let mut uploaded = BTreeMap::new();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .reason("reading the multipart request")
+        .with_message(|| "invalid multipart request".to_string())
+        .status_code(StatusCode::BAD_REQUEST)?
+    {
+        let Some(name) = field.name().map(str::to_string) else {
+            app_error!("reading the multipart request")
+                .err()
+                .with_message(|| "every part must have a name".to_string())
+                .status_code(StatusCode::BAD_REQUEST)?
+        };
+        if uploaded.contains_key(&name) {
+            app_error!("reading the multipart request")
+                .err()
+                .with_message(|| format!("part {name} was uploaded more than once"))
+                .status_code(StatusCode::BAD_REQUEST)?;
+        }
+
+        let expected = expected_hashes(field.headers())?;
+        let (hash, lock) = upload(store, field, &expected).await?;
+        build.locks.insert(hash, lock);
+        tracing::debug!(name, %hash, "part uploaded");
+
+        let location = format!("/api/1/download/{hash}");
+        uploaded.insert(name, UploadedFile { hash, location });
+    }
+
+    Ok((StatusCode::CREATED, Json(uploaded)).into_response())
+}
+
+/// Writes an uploaded file into the store, failing if it doesn't match any of the expected hashes.
+async fn upload<E: Into<anyhow::Error> + 'static>(
+    store: &Store,
+    body: impl Stream<Item = Result<Bytes, E>>,
+    expected: &[SupportedHash],
+) -> Result<(SupportedHash, StoreLock), AppError> {
+    let mut file = store
         .create_file()
         .await
         .reason("creating a temporary file to upload into")?;
-    let mut body = body.into_body().into_data_stream();
+    let mut body = std::pin::pin!(body);
     let mut hash = SupportedHasher::blake3();
 
     tracing::debug!("accepting uploaded data");
@@ -167,13 +413,74 @@ async fn post_file(
     }
 
     let hash = hash.finalize();
-    let final_lock = file
+    if let Some(expected) = expected.iter().find(|expected| **expected != hash) {
+        // Dropping the file removes it.
+        drop(file);
+        app_error!("uploaded file does not match the expected hash")
+            .err()
+            .with_message(|| format!("expected {expected}, but the uploaded file is {hash}"))
+            .status_code(StatusCode::UNPROCESSABLE_ENTITY)?;
+    }
+
+    let lock = file
         .complete(&hash)
         .await
         .reason("committing the file to the store")?;
-    build.locks.insert(final_lock);
+    Ok((hash, lock))
+}
 
-    tracing::debug!(%hash, "file uploaded");
+/// A file to download into the store.
+#[derive(Debug, Deserialize)]
+struct FetchRequest {
+    url: url::Url,
+    hash: SupportedHash,
+}
+
+async fn fetch_file(
+    State(state): State<BuildsState>,
+    Path(build_name): Path<String>,
+    request: Result<Json<FetchRequest>, JsonRejection>,
+) -> Result<Response, AppError> {
+    let Json(FetchRequest { url, hash }) = match request {
+        Ok(request) => request,
+        Err(rejection) => {
+            let message = rejection.body_text();
+            Err(rejection)
+                .reason("parsing the fetch request")
+                .with_message(|| message)
+                .status_code(StatusCode::BAD_REQUEST)?
+        }
+    };
+
+    let build = state
+        .pending_builds
+        .get(&build_name)
+        .ok_or_else_message(|| format!("build {} not found", build_name))?
+        .clone();
+    let mut build = build.lock().await;
+    let build = build.as_mut().ok_or_else_message(|| {
+        format!("build {} has already been submitted for build", build_name)
+    })?;
+
+    let lock = match state.frontend_state.store.fetch(&url, &hash).await {
+        Ok(lock) => lock,
+        Err(error) => {
+            let message = format!("failed to fetch {url}: {error}");
+            let status = match &error {
+                FetchError::UnsupportedScheme(_)
+                | FetchError::FileUrlsDisabled
+                | FetchError::InvalidUrl(_) => StatusCode::BAD_REQUEST,
+                FetchError::Mismatch { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+                FetchError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                _ => StatusCode::BAD_GATEWAY,
+            };
+            Err(error)
+                .reason("fetching the file")
+                .with_message(|| message)
+                .status_code(status)?
+        }
+    };
+    build.locks.insert(hash, lock);
 
     let response = Response::builder()
         .status(StatusCode::CREATED)
@@ -182,15 +489,33 @@ async fn post_file(
         .header("X-Nickelpack-Hash", format!("{hash}"))
         .body(Body::empty())
         .reason("creating response")?;
-
     Ok(response)
 }
 
 async fn run(
     State(state): State<BuildsState>,
     Path(build_name): Path<String>,
-    Json(body): Json<Spec>,
+    spec: Result<Json<Spec>, JsonRejection>,
 ) -> Result<Response, AppError> {
+    let Json(spec) = match spec {
+        Ok(spec) => spec,
+        Err(rejection) => {
+            let message = rejection.body_text();
+            Err(rejection)
+                .reason("parsing the build spec")
+                .with_message(|| message)
+                .status_code(StatusCode::BAD_REQUEST)?
+        }
+    };
+
+    if let Err(error) = spec.validate() {
+        let message = error.to_string();
+        Err(error)
+            .reason("validating the build spec")
+            .with_message(|| message)
+            .status_code(StatusCode::BAD_REQUEST)?;
+    }
+
     let pending = state
         .pending_builds
         .remove(&build_name)
@@ -200,17 +525,19 @@ async fn run(
         .await
         .ok_or_else_message(|| format!("build {} has already been submitted", build_name))?;
 
-    let hash = body.hash(SupportedHasher::blake3());
-
-    state
-        .0
-        .frontend_state
-        .store
-        .clone()
-        .start(body)
-        .await
-        .reason("starting the build")?;
-    drop(pending);
+    let hash = match state.0.frontend_state.store.clone().start(spec).await {
+        Ok(hash) => hash,
+        Err(error) => {
+            // The files are still locked, so the build can be run again once the problem has been fixed.
+            state
+                .pending_builds
+                .insert(build_name, PendingBuild::new(pending));
+            return Err(error).reason("starting the build");
+        }
+    };
+    if let Err(error) = pending.discard().await {
+        tracing::warn!(build_name, ?error, "failed to remove the working directory");
+    }
 
     let response = Response::builder()
         .status(StatusCode::ACCEPTED)
@@ -220,3 +547,191 @@ async fn run(
         .reason("creating response")?;
     Ok(response)
 }
+
+#[cfg(test)]
+mod test {
+    use axum::{body::Body, Router};
+    use hyper::{Method, Request, StatusCode};
+    use nck_hashing::{SupportedHash, SupportedHasher};
+    use serde_json::{json, Value};
+
+    use crate::frontend::{send, test_app};
+
+    const MULTIPART: &str = "multipart/form-data; boundary=xyz";
+
+    fn request(method: Method, uri: &str, body: impl Into<Body>) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(body.into())
+            .unwrap()
+    }
+
+    async fn create_build(app: &Router) -> String {
+        let response = send(app, request(Method::POST, "/api/1/build", Body::empty())).await;
+        assert_eq!(StatusCode::CREATED, response.status());
+        let location = response.headers()["location"].to_str().unwrap();
+        location.strip_prefix("/api/1/build/").unwrap().to_string()
+    }
+
+    async fn get_json(app: &Router, uri: &str) -> (StatusCode, Value) {
+        let response = send(app, request(Method::GET, uri, Body::empty())).await;
+        let json = serde_json::from_slice(response.body()).unwrap_or(Value::Null);
+        (response.status(), json)
+    }
+
+    async fn upload_file(app: &Router, name: &str, contents: &'static [u8]) -> SupportedHash {
+        let uri = format!("/api/1/build/{name}/file");
+        let response = send(app, request(Method::POST, &uri, contents)).await;
+        assert_eq!(StatusCode::CREATED, response.status());
+        response.headers()["x-nickelpack-hash"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+
+    /// Encodes parts as `multipart/form-data` with the boundary in `MULTIPART`.
+    fn multipart(parts: &[(&str, Option<SupportedHash>, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (name, expected, contents) in parts {
+            body.extend_from_slice(
+                format!("--xyz\r\nContent-Disposition: form-data; name=\"{name}\"\r\n").as_bytes(),
+            );
+            if let Some(expected) = expected {
+                body.extend_from_slice(
+                    format!("X-Nickelpack-Expected-Hash: {expected}\r\n").as_bytes(),
+                );
+            }
+            body.extend_from_slice(b"\r\n");
+            body.extend_from_slice(contents);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--xyz--\r\n");
+        body
+    }
+
+    async fn post_multipart(app: &Router, name: &str, body: Vec<u8>) -> (StatusCode, Value) {
+        let request = Request::post(format!("/api/1/build/{name}/file"))
+            .header("content-type", MULTIPART)
+            .body(Body::from(body))
+            .unwrap();
+        let response = send(app, request).await;
+        let json = serde_json::from_slice(response.body()).unwrap_or(Value::Null);
+        (response.status(), json)
+    }
+
+    #[tokio::test]
+    async fn list_get_and_delete() {
+        let (dir, app) = test_app().await;
+
+        let first = create_build(&app).await;
+        let second = create_build(&app).await;
+        let mut names = vec![first.clone(), second.clone()];
+        names.sort();
+        assert_eq!(
+            (StatusCode::OK, json!(names)),
+            get_json(&app, "/api/1/build").await
+        );
+
+        let hash = upload_file(&app, &first, b"hello").await;
+        assert_eq!(
+            (StatusCode::OK, json!({ "name": first, "files": [hash] })),
+            get_json(&app, &format!("/api/1/build/{first}")).await
+        );
+
+        let uri = format!("/api/1/build/{first}");
+        let response = send(&app, request(Method::DELETE, &uri, Body::empty())).await;
+        assert_eq!(StatusCode::NO_CONTENT, response.status());
+        assert_eq!(json!([second]), get_json(&app, "/api/1/build").await.1);
+        assert!(!dir.as_path().join("temp").join(&first).exists());
+        assert_eq!(StatusCode::NOT_FOUND, get_json(&app, &uri).await.0);
+        let response = send(&app, request(Method::DELETE, &uri, Body::empty())).await;
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+    }
+
+    #[tokio::test]
+    async fn failed_start_keeps_the_build() {
+        let (dir, app) = test_app().await;
+        let name = create_build(&app).await;
+        let hash = upload_file(&app, &name, b"hello").await;
+
+        // The dependency has never been built, so the build can't start.
+        let spec = json!({
+            "name": "pkg",
+            "outputs": ["out"],
+            "dependencies": [{ "build": hash, "output": "out" }],
+        });
+        let run = Request::post(format!("/api/1/build/{name}/run"))
+            .header("content-type", "application/json")
+            .body(Body::from(spec.to_string()))
+            .unwrap();
+        assert!(send(&app, run).await.status().is_server_error());
+
+        assert_eq!(
+            json!([hash]),
+            get_json(&app, &format!("/api/1/build/{name}")).await.1["files"]
+        );
+        assert!(dir.as_path().join("temp").join(&name).exists());
+    }
+
+    #[tokio::test]
+    async fn upload_parts() {
+        let (dir, app) = test_app().await;
+        let name = create_build(&app).await;
+
+        // Larger than the default body limit of axum.
+        let large = vec![7u8; 3 * 1024 * 1024];
+        let mut hasher = SupportedHasher::blake3();
+        hasher.update(&large);
+        let large_hash = hasher.finalize();
+        let body = multipart(&[
+            ("small", None, b"hello"),
+            ("large", Some(large_hash), &large),
+            ("empty", None, b""),
+        ]);
+        let (status, uploaded) = post_multipart(&app, &name, body).await;
+        assert_eq!(StatusCode::CREATED, status);
+        let uploaded = uploaded.as_object().unwrap();
+        assert_eq!(
+            vec!["empty", "large", "small"],
+            uploaded.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            json!({
+                "hash": large_hash,
+                "location": format!("/api/1/download/{large_hash}"),
+            }),
+            uploaded["large"]
+        );
+        let stored = dir.as_path().join(format!("files/{large_hash}"));
+        assert_eq!(3 * 1024 * 1024, std::fs::metadata(stored).unwrap().len());
+
+        let (_, build) = get_json(&app, &format!("/api/1/build/{name}")).await;
+        let mut files: Vec<_> = build["files"].as_array().unwrap().iter().collect();
+        files.sort_by_key(|hash| hash.to_string());
+        let mut expected: Vec<_> = uploaded.values().map(|file| &file["hash"]).collect();
+        expected.sort_by_key(|hash| hash.to_string());
+        assert_eq!(expected, files);
+    }
+
+    #[tokio::test]
+    async fn reject_bad_parts() {
+        let (_dir, app) = test_app().await;
+        let name = create_build(&app).await;
+
+        let body = multipart(&[("same", None, b"a"), ("same", None, b"b")]);
+        let (status, _) = post_multipart(&app, &name, body).await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+
+        let wrong = SupportedHasher::blake3().finalize();
+        let body = multipart(&[("file", Some(wrong), b"hello")]);
+        let (status, _) = post_multipart(&app, &name, body).await;
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, status);
+
+        // The build can still be used.
+        let body = multipart(&[("file", None, b"hello")]);
+        let (status, _) = post_multipart(&app, &name, body).await;
+        assert_eq!(StatusCode::CREATED, status);
+    }
+}
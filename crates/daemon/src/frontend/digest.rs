@@ -0,0 +1,109 @@
+use data_encoding::BASE64;
+use hyper::{HeaderMap, StatusCode};
+use nck_hashing::SupportedHash;
+use thiserror::Error;
+
+use crate::axum_extensions::{AppError, AppErrorReason};
+
+/// The hash that the client expects an upload to have, such as `blake3-...`.
+pub const EXPECTED_HASH: &str = "X-Nickelpack-Expected-Hash";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum DigestError {
+    #[error("expected a digest such as blake3=:...:")]
+    Malformed,
+    #[error("invalid base64 in the {0} digest")]
+    InvalidBase64(String),
+    #[error("the {0} digest has the wrong length")]
+    InvalidLength(String),
+}
+
+/// Parses a `Digest` (RFC 3230) or, if `structured`, a `Content-Digest` (RFC 9530) header value.
+///
+/// Algorithms that aren't supported are ignored, because they can't be checked.
+fn parse_digest(value: &str, structured: bool) -> Result<Vec<SupportedHash>, DigestError> {
+    let mut result = Vec::new();
+    for member in value.split(',').map(str::trim).filter(|v| !v.is_empty()) {
+        let (algorithm, digest) = member.split_once('=').ok_or(DigestError::Malformed)?;
+        let algorithm = algorithm.trim().to_ascii_lowercase();
+        let digest = digest.split(';').next().unwrap_or_default().trim();
+        let digest = if structured {
+            digest
+                .strip_prefix(':')
+                .and_then(|v| v.strip_suffix(':'))
+                .ok_or(DigestError::Malformed)?
+        } else {
+            digest
+        };
+
+        if algorithm != "blake3" {
+            tracing::trace!(algorithm, "ignoring unsupported digest");
+            continue;
+        }
+        let bytes = BASE64
+            .decode(digest.as_bytes())
+            .map_err(|_| DigestError::InvalidBase64(algorithm.clone()))?;
+        let bytes = bytes
+            .try_into()
+            .map_err(|_| DigestError::InvalidLength(algorithm))?;
+        result.push(SupportedHash::Blake3(bytes));
+    }
+    Ok(result)
+}
+
+/// Collects every hash that the client expects an upload to have.
+pub fn expected_hashes(header_map: &HeaderMap) -> Result<Vec<SupportedHash>, AppError> {
+    let mut result = Vec::new();
+
+    for value in header_map.get_all(EXPECTED_HASH) {
+        let hash = value
+            .to_str()
+            .reason("parsing expected hash")
+            .and_then(|value| value.trim().parse().reason("parsing expected hash"))
+            .with_message(|| format!("invalid {EXPECTED_HASH} value"))
+            .status_code(StatusCode::BAD_REQUEST)?;
+        result.push(hash);
+    }
+
+    for (name, structured) in [("Content-Digest", true), ("Digest", false)] {
+        for value in header_map.get_all(name) {
+            let hashes = value
+                .to_str()
+                .reason("parsing digest")
+                .and_then(|value| parse_digest(value, structured).reason("parsing digest"))
+                .with_message(|| format!("invalid {name} value"))
+                .status_code(StatusCode::BAD_REQUEST)?;
+            result.extend(hashes);
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use nck_hashing::SupportedHash;
+    use rstest::rstest;
+
+    use super::{parse_digest, DigestError};
+
+    const HASH: SupportedHash = SupportedHash::Blake3([1; 32]);
+    const ENCODED: &str = "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=";
+
+    #[rstest]
+    #[case(format!("blake3=:{ENCODED}:"), true, Ok(vec![HASH]))]
+    #[case(format!("sha-256=:AAAA:, BLAKE3=:{ENCODED}:"), true, Ok(vec![HASH]))]
+    #[case(format!("blake3={ENCODED}"), false, Ok(vec![HASH]))]
+    #[case("sha-256=AAAA".to_string(), false, Ok(vec![]))]
+    #[case(format!("blake3={ENCODED}"), true, Err(DigestError::Malformed))]
+    #[case("blake3".to_string(), false, Err(DigestError::Malformed))]
+    #[case("blake3=:AAAA:".to_string(), true, Err(DigestError::InvalidLength("blake3".into())))]
+    #[case("blake3=!".to_string(), false, Err(DigestError::InvalidBase64("blake3".into())))]
+    fn digest(
+        #[case] value: String,
+        #[case] structured: bool,
+        #[case] expected: Result<Vec<SupportedHash>, DigestError>,
+    ) {
+        assert_eq!(expected, parse_digest(&value, structured));
+    }
+}
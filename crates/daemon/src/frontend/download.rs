@@ -0,0 +1,185 @@
+use std::io::{ErrorKind, SeekFrom};
+
+use axum::{
+    extract::{Path, State},
+    routing::get,
+    Router,
+};
+use axum_core::{body::Body, response::Response};
+use hyper::{header, HeaderMap, StatusCode};
+use nck_hashing::SupportedHash;
+use tokio::io::{AsyncReadExt as _, AsyncSeekExt as _};
+use tokio_util::io::ReaderStream;
+
+use crate::axum_extensions::{AppError, AppErrorReason};
+
+use super::FrontendState;
+
+pub fn create_routes(frontend_state: FrontendState) -> Router {
+    Router::new()
+        .route("/:hash", get(download))
+        .with_state(frontend_state)
+}
+
+/// A single byte range, as requested by a `Range` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteRange {
+    /// The inclusive range of bytes to send.
+    Satisfiable {
+        start: u64,
+        end: u64,
+    },
+    Unsatisfiable,
+}
+
+/// Parses a `Range` header for a file of the given length.
+///
+/// Only a single range is supported. Multiple or malformed ranges return `None`, in which case the header is ignored
+/// and the entire file is sent.
+fn parse_range(value: &str, len: u64) -> Option<ByteRange> {
+    let spec = value.trim().strip_prefix("bytes=")?.trim();
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let (start, end) = if start.is_empty() {
+        let suffix: u64 = end.parse().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(ByteRange::Unsatisfiable);
+        }
+        (len.saturating_sub(suffix), len - 1)
+    } else {
+        let start: u64 = start.parse().ok()?;
+        let end = if end.is_empty() {
+            u64::MAX
+        } else {
+            end.parse().ok()?
+        };
+        if end < start {
+            return None;
+        }
+        if start >= len {
+            return Some(ByteRange::Unsatisfiable);
+        }
+        (start, end.min(len - 1))
+    };
+
+    Some(ByteRange::Satisfiable { start, end })
+}
+
+/// Checks whether an `If-None-Match` header matches the entity tag.
+fn matches_etag(value: &str, etag: &str) -> bool {
+    value.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+async fn download(
+    State(state): State<FrontendState>,
+    Path(hash): Path<SupportedHash>,
+    header_map: HeaderMap,
+) -> Result<Response, AppError> {
+    let etag = format!("\"{hash}\"");
+
+    let mut file = match state.store.get_file(&hash).await {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => Err(e)
+            .reason("opening the store file")
+            .with_message(|| format!("file {hash} not found"))
+            .status_code(StatusCode::NOT_FOUND)?,
+        Err(other) => Err(other).reason("opening the store file")?,
+    };
+
+    if let Some(value) = header_map.get(header::IF_NONE_MATCH) {
+        let value = value
+            .to_str()
+            .reason("parsing If-None-Match value")
+            .with_message(|| "invalid If-None-Match value".to_string())
+            .status_code(StatusCode::BAD_REQUEST)?;
+        if matches_etag(value, &etag) {
+            tracing::trace!(%hash, "file not modified");
+            let response = Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .header(header::ETAG, etag)
+                .body(Body::empty())
+                .reason("creating response")?;
+            return Ok(response);
+        }
+    }
+
+    let len = file
+        .metadata()
+        .await
+        .reason("reading the store file metadata")?
+        .len();
+
+    let range = header_map
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_range(value, len));
+
+    let response = Response::builder()
+        .header(header::ETAG, etag)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CONTENT_TYPE, "application/octet-stream");
+
+    let response = match range {
+        None => response
+            .status(StatusCode::OK)
+            .header(header::CONTENT_LENGTH, len)
+            .body(Body::from_stream(ReaderStream::new(file))),
+        Some(ByteRange::Satisfiable { start, end }) => {
+            tracing::trace!(%hash, start, end, "sending partial file");
+            file.seek(SeekFrom::Start(start))
+                .await
+                .reason("seeking the store file")?;
+            let count = end - start + 1;
+            response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_LENGTH, count)
+                .header(header::CONTENT_RANGE, format!("bytes {start}-{end}/{len}"))
+                .body(Body::from_stream(ReaderStream::new(file.take(count))))
+        }
+        Some(ByteRange::Unsatisfiable) => response
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{len}"))
+            .body(Body::empty()),
+    };
+
+    response.reason("creating response")
+}
+
+#[cfg(test)]
+mod test {
+    use rstest::rstest;
+
+    use super::{matches_etag, parse_range, ByteRange};
+
+    #[rstest]
+    #[case("bytes=0-99", Some(ByteRange::Satisfiable { start: 0, end: 99 }))]
+    #[case("bytes=100-", Some(ByteRange::Satisfiable { start: 100, end: 999 }))]
+    #[case("bytes=900-2000", Some(ByteRange::Satisfiable { start: 900, end: 999 }))]
+    #[case("bytes=-100", Some(ByteRange::Satisfiable { start: 900, end: 999 }))]
+    #[case("bytes=-2000", Some(ByteRange::Satisfiable { start: 0, end: 999 }))]
+    #[case("bytes=1000-", Some(ByteRange::Unsatisfiable))]
+    #[case("bytes=-0", Some(ByteRange::Unsatisfiable))]
+    #[case("bytes=10-5", None)]
+    #[case("bytes=0-1,5-6", None)]
+    #[case("items=0-1", None)]
+    #[case("bytes=a-b", None)]
+    fn range(#[case] value: &str, #[case] expected: Option<ByteRange>) {
+        assert_eq!(expected, parse_range(value, 1000));
+    }
+
+    #[rstest]
+    #[case("\"blake3-a\"", true)]
+    #[case("W/\"blake3-a\"", true)]
+    #[case("\"blake3-b\", \"blake3-a\"", true)]
+    #[case("*", true)]
+    #[case("\"blake3-b\"", false)]
+    fn etag(#[case] value: &str, #[case] expected: bool) {
+        assert_eq!(expected, matches_etag(value, "\"blake3-a\""));
+    }
+}
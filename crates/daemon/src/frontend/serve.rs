@@ -111,7 +111,7 @@ where
         tracing::trace!(?socket_path, "cleaning up previous socket");
         tokio::fs::remove_file(&settings.socket_path)
             .await
-            .with_context(|| format!("failed to bind to {:?}", &settings.socket_path))?;
+            .with_context(|| format!("failed to bind to {:?}", settings.socket_path))?;
     }
 
     tracing::trace!(?socket_path, "binding");
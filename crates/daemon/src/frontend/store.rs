@@ -0,0 +1,289 @@
+use std::{collections::BTreeMap, io::ErrorKind};
+
+use axum::{
+    extract::{rejection::JsonRejection, Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use axum_core::{body::Body, response::Response};
+use hyper::{header, StatusCode};
+use nck_hashing::SupportedHash;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_error,
+    axum_extensions::{AppError, AppErrorOption, AppErrorReason},
+    spec::RootName,
+    store::{BuildRecord, BuildStatus, FileRecord, GcReport, VerifyReport},
+};
+
+use super::FrontendState;
+
+pub fn create_routes(frontend_state: FrontendState) -> Router {
+    Router::new()
+        .route("/status/:hash", get(status))
+        .route("/log/:hash", get(log))
+        .route("/build/:hash", get(build).delete(cancel))
+        .route("/file/:hash", get(file))
+        .route("/missing", post(missing))
+        .route("/gc", post(gc))
+        .route("/verify", post(verify))
+        .route("/roots", get(roots))
+        .route(
+            "/roots/:name",
+            get(get_root).put(put_root).delete(delete_root),
+        )
+        .with_state(frontend_state)
+}
+
+async fn status(
+    State(state): State<FrontendState>,
+    Path(hash): Path<SupportedHash>,
+) -> Result<Json<BuildStatus>, AppError> {
+    let status = state
+        .store
+        .status(&hash)
+        .ok_or_else_message(|| format!("build {hash} not found"))?;
+    Ok(Json(status))
+}
+
+async fn build(
+    State(state): State<FrontendState>,
+    Path(hash): Path<SupportedHash>,
+) -> Result<Json<BuildRecord>, AppError> {
+    let record = state
+        .store
+        .build_record(&hash)
+        .ok_or_else_message(|| format!("build {hash} not found"))?;
+    Ok(Json(record))
+}
+
+async fn file(
+    State(state): State<FrontendState>,
+    Path(hash): Path<SupportedHash>,
+) -> Result<Json<FileRecord>, AppError> {
+    let record = state
+        .store
+        .file_record(&hash)
+        .ok_or_else_message(|| format!("file {hash} not found"))?;
+    Ok(Json(record))
+}
+
+/// Lists the files that the store doesn't have, so that only those need to be uploaded.
+async fn missing(
+    State(state): State<FrontendState>,
+    hashes: Result<Json<Vec<SupportedHash>>, JsonRejection>,
+) -> Result<Json<Vec<SupportedHash>>, AppError> {
+    let Json(hashes) = match hashes {
+        Ok(hashes) => hashes,
+        Err(rejection) => {
+            let message = rejection.body_text();
+            Err(rejection)
+                .reason("parsing the list of hashes")
+                .with_message(|| message)
+                .status_code(StatusCode::BAD_REQUEST)?
+        }
+    };
+    let missing = state
+        .store
+        .missing(&hashes)
+        .await
+        .reason("querying the store")?;
+    Ok(Json(missing))
+}
+
+async fn log(
+    State(state): State<FrontendState>,
+    Path(hash): Path<SupportedHash>,
+) -> Result<Response, AppError> {
+    let log = state
+        .store
+        .log(&hash)
+        .ok_or_else_message(|| format!("build {hash} not found"))?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Body::from_stream(log.stream()))
+        .reason("creating response")?;
+    Ok(response)
+}
+
+async fn cancel(
+    State(state): State<FrontendState>,
+    Path(hash): Path<SupportedHash>,
+) -> Result<Response, AppError> {
+    let previous = state
+        .store
+        .cancel(&hash)
+        .await
+        .reason("cancelling the build")?
+        .ok_or_else_message(|| format!("build {hash} not found"))?;
+
+    if !matches!(previous, BuildStatus::Queued | BuildStatus::Running) {
+        app_error!("build has already finished")
+            .err()
+            .with_message(|| format!("build {hash} has already finished"))
+            .status_code(StatusCode::CONFLICT)?;
+    }
+
+    let response = Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .reason("creating response")?;
+    Ok(response)
+}
+
+#[derive(Debug, Deserialize)]
+struct GcQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+async fn gc(
+    State(state): State<FrontendState>,
+    Query(query): Query<GcQuery>,
+) -> Result<Json<GcReport>, AppError> {
+    let report = state
+        .store
+        .collect_garbage(query.dry_run)
+        .await
+        .reason("collecting garbage")?;
+    Ok(Json(report))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RootTarget {
+    hash: SupportedHash,
+}
+
+async fn roots(State(state): State<FrontendState>) -> Json<BTreeMap<RootName, SupportedHash>> {
+    Json(state.store.roots())
+}
+
+async fn get_root(
+    State(state): State<FrontendState>,
+    Path(name): Path<RootName>,
+) -> Result<Json<RootTarget>, AppError> {
+    let hash = state
+        .store
+        .root(&name)
+        .ok_or_else_message(|| format!("root {name} not found"))?;
+    Ok(Json(RootTarget { hash }))
+}
+
+async fn put_root(
+    State(state): State<FrontendState>,
+    Path(name): Path<RootName>,
+    Json(target): Json<RootTarget>,
+) -> Result<Response, AppError> {
+    let hash = target.hash;
+    let previous = match state.store.set_root(name, hash).await {
+        Ok(previous) => previous,
+        Err(e) if e.kind() == ErrorKind::NotFound => Err(e)
+            .reason("setting the root")
+            .with_message(|| format!("file {hash} not found"))
+            .status_code(StatusCode::NOT_FOUND)?,
+        Err(other) => Err(other).reason("setting the root")?,
+    };
+
+    let status = if previous.is_some() {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::CREATED
+    };
+    let response = Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .reason("creating response")?;
+    Ok(response)
+}
+
+async fn delete_root(
+    State(state): State<FrontendState>,
+    Path(name): Path<RootName>,
+) -> Result<Response, AppError> {
+    state
+        .store
+        .remove_root(&name)
+        .await
+        .reason("removing the root")?
+        .ok_or_else_message(|| format!("root {name} not found"))?;
+
+    let response = Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(Body::empty())
+        .reason("creating response")?;
+    Ok(response)
+}
+
+#[derive(Debug, Deserialize)]
+struct VerifyQuery {
+    #[serde(default)]
+    quarantine: bool,
+}
+
+async fn verify(
+    State(state): State<FrontendState>,
+    Query(query): Query<VerifyQuery>,
+) -> Result<Json<VerifyReport>, AppError> {
+    let report = state
+        .store
+        .verify(query.quarantine)
+        .await
+        .reason("verifying the store")?;
+    Ok(Json(report))
+}
+
+#[cfg(test)]
+mod test {
+    use axum::body::Body;
+    use hyper::{Request, StatusCode};
+    use nck_hashing::{SupportedHash, SupportedHasher};
+
+    use crate::frontend::{send, test_app};
+
+    fn hash(contents: &str) -> SupportedHash {
+        let mut hasher = SupportedHasher::blake3();
+        hasher.update(contents.as_bytes());
+        hasher.finalize()
+    }
+
+    #[tokio::test]
+    async fn missing_keeps_order() {
+        let (dir, app) = test_app().await;
+        let present = hash("present");
+        std::fs::write(dir.as_path().join(format!("files/{present}")), "present").unwrap();
+
+        let missing = |hashes: Vec<SupportedHash>| {
+            let request = Request::post("/api/1/store/missing")
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::to_vec(&hashes).unwrap()))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = send(&app, request).await;
+                assert_eq!(StatusCode::OK, response.status());
+                serde_json::from_slice::<Vec<SupportedHash>>(response.body()).unwrap()
+            }
+        };
+
+        let (c, a, b) = (hash("c"), hash("a"), hash("b"));
+        assert_eq!(vec![c, a, b], missing(vec![c, present, a, c, b, a]).await);
+        assert!(missing(vec![]).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn missing_rejects_invalid_json() {
+        let (_dir, app) = test_app().await;
+        let request = Request::post("/api/1/store/missing")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"["not a hash"]"#))
+            .unwrap();
+
+        let response = send(&app, request).await;
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let error: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert!(error["message"].is_string(), "{error}");
+    }
+}
@@ -1,7 +1,10 @@
-#![feature(never_type)]
 #![feature(register_tool)]
 #![feature(custom_inner_attributes)]
 #![register_tool(tarpaulin)]
+// TODO: Remove once the sandbox is fully wired up.
+#![allow(dead_code)]
+
+use std::time::Duration;
 
 use build::linux::PendingController;
 use settings::Settings;
@@ -12,6 +15,7 @@ mod axum_extensions;
 mod build;
 mod frontend;
 mod settings;
+mod spec;
 mod store;
 mod string_types;
 
@@ -27,7 +31,9 @@ fn main() -> anyhow::Result<()> {
         .add_source(
             config::Environment::with_prefix("nck")
                 .separator("__")
-                .try_parsing(true),
+                .try_parsing(true)
+                .list_separator(",")
+                .with_list_parse_key("store.gc.pinned"),
         )
         .build()?
         .try_deserialize()?;
@@ -44,6 +50,14 @@ async fn async_main(controller: PendingController, settings: Settings) -> anyhow
     let controller = controller.into_controller().await?;
     let store = Store::new(controller, &settings.store).await?;
 
+    if let Some(interval) = settings.store.gc.interval {
+        tokio::spawn(
+            store
+                .clone()
+                .collect_periodically(Duration::from_secs(interval.get())),
+        );
+    }
+
     let front_end = tokio::spawn(frontend::frontend(store.clone(), settings.clone()));
 
     front_end.await?
@@ -1,5 +1,6 @@
-use std::path::PathBuf;
+use std::{num::NonZeroU64, path::PathBuf};
 
+use nck_hashing::SupportedHash;
 use serde::Deserialize;
 
 #[derive(Debug, Clone, Deserialize)]
@@ -19,10 +20,31 @@ pub struct TcpSettings {
     pub bind: Vec<String>,
 }
 
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct GcSettings {
+    /// How often, in seconds, garbage collection runs. It only runs when requested if this is not set, and can't be
+    /// zero, which would collect continuously and starve builds of the store.
+    pub interval: Option<NonZeroU64>,
+    /// Files that are never collected.
+    #[serde(default)]
+    pub pinned: Vec<SupportedHash>,
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct FetchSettings {
+    /// Lets clients fetch `file://` URLs, which can read anything that the daemon can.
+    #[serde(default)]
+    pub allow_file_urls: bool,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct StoreSettings {
     pub path: PathBuf,
     pub temp: PathBuf,
+    #[serde(default)]
+    pub gc: GcSettings,
+    #[serde(default)]
+    pub fetch: FetchSettings,
 }
 
 impl Default for StoreSettings {
@@ -30,6 +52,8 @@ impl Default for StoreSettings {
         Self {
             path: PathBuf::from("/var/nck/store"),
             temp: std::env::temp_dir().join("nck"),
+            gc: GcSettings::default(),
+            fetch: FetchSettings::default(),
         }
     }
 }
@@ -58,3 +82,15 @@ pub struct Settings {
 
     pub daemon: DaemonSettings,
 }
+
+#[cfg(test)]
+mod test {
+    use super::GcSettings;
+
+    #[test]
+    fn gc_interval() {
+        let settings: GcSettings = toml::from_str("interval = 60").unwrap();
+        assert_eq!(Some(60), settings.interval.map(|interval| interval.get()));
+        assert!(toml::from_str::<GcSettings>("interval = 0").is_err());
+    }
+}
@@ -0,0 +1,453 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use nck_hashing::{StableHash, StableHashExt, StableHasher, StableHasherExt, SupportedHash};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const MAX_NAME_LENGTH: usize = 128;
+
+#[derive(Debug, Error)]
+pub enum SpecError {
+    #[error("invalid name {0:?}, names must be 1 to 128 characters of a-z, A-Z, 0-9, '.', '_', '+' or '-' and must not start with '.'")]
+    InvalidName(String),
+    #[error("at least one output is required")]
+    NoOutputs,
+    #[error("action {index} is invalid: {reason}")]
+    InvalidAction { index: usize, reason: &'static str },
+}
+
+fn validate_name(value: &str) -> Result<(), SpecError> {
+    let valid = !value.is_empty()
+        && value.len() <= MAX_NAME_LENGTH
+        && !value.starts_with('.')
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'+' | b'-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(SpecError::InvalidName(value.to_string()))
+    }
+}
+
+macro_rules! name_type {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+        #[serde(try_from = "String", into = "String")]
+        pub struct $name(String);
+
+        impl FromStr for $name {
+            type Err = SpecError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                validate_name(s)?;
+                Ok(Self(s.to_string()))
+            }
+        }
+
+        impl TryFrom<String> for $name {
+            type Error = SpecError;
+
+            fn try_from(value: String) -> Result<Self, Self::Error> {
+                validate_name(&value)?;
+                Ok(Self(value))
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl StableHash for $name {
+            #[inline(always)]
+            fn update<H: StableHasher>(&self, h: &mut H) {
+                self.0.update(h)
+            }
+        }
+    };
+}
+
+name_type!(
+    /// The name of a package, such as `bootstrap-0.0.1`.
+    PackageName
+);
+
+name_type!(
+    /// The name of a package output, such as `out`.
+    OutputName
+);
+
+name_type!(
+    /// The name of a garbage collection root, such as `bootstrap-rootfs`.
+    RootName
+);
+
+/// A single step executed inside the sandbox.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// Sets an environment variable for subsequent actions.
+    Set { name: String, value: String },
+    /// Changes the working directory for subsequent actions.
+    WorkDir { path: PathBuf },
+    /// Links a store file into the sandbox.
+    ///
+    /// Store files are read-only and a symlink has no mode of its own, so a link can't be made executable.
+    /// `executable` is only accepted so that validation can point at [`Action::Copy`] instead.
+    Link {
+        from: PathBuf,
+        to: PathBuf,
+        #[serde(default)]
+        executable: bool,
+    },
+    /// Copies a store file into the sandbox.
+    Copy {
+        from: PathBuf,
+        to: PathBuf,
+        #[serde(default)]
+        executable: bool,
+    },
+    /// Executes a command and waits for it to exit.
+    Exec {
+        path: PathBuf,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+}
+
+impl Action {
+    fn validate(&self) -> Result<(), &'static str> {
+        fn absolute(path: &Path, reason: &'static str) -> Result<(), &'static str> {
+            if path.is_absolute() {
+                Ok(())
+            } else {
+                Err(reason)
+            }
+        }
+
+        fn no_nul(value: &str, reason: &'static str) -> Result<(), &'static str> {
+            if value.contains('\0') {
+                Err(reason)
+            } else {
+                Ok(())
+            }
+        }
+
+        match self {
+            Action::Set { name, value } => {
+                if name.is_empty() || name.contains('=') {
+                    return Err("environment variable names must be non-empty and not contain '='");
+                }
+                no_nul(name, "environment variable names must not contain NUL")?;
+                no_nul(value, "environment variable values must not contain NUL")
+            }
+            Action::WorkDir { path } => absolute(path, "the working directory must be absolute"),
+            Action::Link {
+                executable: true, ..
+            } => Err("links can't be executable, copy the file instead"),
+            Action::Link { from, to, .. } | Action::Copy { from, to, .. } => {
+                absolute(from, "the source path must be absolute")?;
+                absolute(to, "the destination path must be absolute")
+            }
+            Action::Exec { path, args } => {
+                if path.as_os_str().is_empty() {
+                    return Err("the executable path must not be empty");
+                }
+                args.iter()
+                    .try_for_each(|v| no_nul(v, "arguments must not contain NUL"))
+            }
+        }
+    }
+}
+
+impl StableHash for Action {
+    fn update<H: StableHasher>(&self, h: &mut H) {
+        match self {
+            Action::Set { name, value } => h.update_hash(1u8).update_hash(name).update_hash(value),
+            Action::WorkDir { path } => h.update_hash(2u8).update_hash(path),
+            Action::Link {
+                from,
+                to,
+                executable,
+            } => h
+                .update_hash(3u8)
+                .update_hash(from)
+                .update_hash(to)
+                .update_hash(executable),
+            Action::Copy {
+                from,
+                to,
+                executable,
+            } => h
+                .update_hash(4u8)
+                .update_hash(from)
+                .update_hash(to)
+                .update_hash(executable),
+            Action::Exec { path, args } => h.update_hash(5u8).update_hash(path).update_hash(args),
+        };
+    }
+}
+
+/// An output of another build.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputReference {
+    /// The hash of the spec that produced the output.
+    pub build: SupportedHash,
+    pub output: OutputName,
+}
+
+impl StableHash for OutputReference {
+    fn update<H: StableHasher>(&self, h: &mut H) {
+        h.update_hash(self.build).update_hash(&self.output);
+    }
+}
+
+fn output_path(store: &Path, build: &SupportedHash, output: &OutputName) -> PathBuf {
+    store.join("outputs").join(format!("{build}-{output}"))
+}
+
+/// Something in the store that a build requires.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Dependency {
+    /// A file under `files/`.
+    File(SupportedHash),
+    /// An output under `outputs/`.
+    Output(OutputReference),
+}
+
+impl Dependency {
+    /// The location of the dependency within the store.
+    pub fn path(&self, store: impl AsRef<Path>) -> PathBuf {
+        match self {
+            Dependency::File(hash) => store.as_ref().join("files").join(hash.to_string()),
+            Dependency::Output(output) => {
+                output_path(store.as_ref(), &output.build, &output.output)
+            }
+        }
+    }
+}
+
+/// The locations of the files that belong to a spec.
+#[derive(Debug, Clone)]
+pub struct SpecPaths {
+    hash: SupportedHash,
+    root: PathBuf,
+    store: PathBuf,
+}
+
+impl SpecPaths {
+    pub fn hash(&self) -> &SupportedHash {
+        &self.hash
+    }
+
+    /// The directory containing the build records for the spec.
+    pub fn root(&self) -> &Path {
+        self.root.as_path()
+    }
+
+    /// The serialized spec.
+    pub fn spec(&self) -> PathBuf {
+        self.root.join("spec.toml")
+    }
+
+    /// The log of the build.
+    pub fn log(&self) -> PathBuf {
+        self.root.join("log")
+    }
+
+    /// The location of an output once the build has succeeded.
+    pub fn output(&self, name: &OutputName) -> PathBuf {
+        output_path(&self.store, &self.hash, name)
+    }
+}
+
+/// A build specification.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spec {
+    name: PackageName,
+    outputs: BTreeSet<OutputName>,
+    #[serde(default)]
+    files: BTreeSet<SupportedHash>,
+    #[serde(default)]
+    dependencies: BTreeSet<OutputReference>,
+    #[serde(default)]
+    actions: Vec<Action>,
+}
+
+impl Spec {
+    pub fn name(&self) -> &PackageName {
+        &self.name
+    }
+
+    pub fn outputs(&self) -> &BTreeSet<OutputName> {
+        &self.outputs
+    }
+
+    pub fn files(&self) -> &BTreeSet<SupportedHash> {
+        &self.files
+    }
+
+    /// The outputs of other builds that are required.
+    pub fn output_dependencies(&self) -> &BTreeSet<OutputReference> {
+        &self.dependencies
+    }
+
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
+    /// Checks the parts of the spec that can't be expressed by the types alone.
+    pub fn validate(&self) -> Result<(), SpecError> {
+        if self.outputs.is_empty() {
+            return Err(SpecError::NoOutputs);
+        }
+
+        for (index, action) in self.actions.iter().enumerate() {
+            action
+                .validate()
+                .map_err(|reason| SpecError::InvalidAction { index, reason })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn paths<H: StableHasher<Result = SupportedHash>>(
+        &self,
+        store: impl AsRef<Path>,
+        hasher: H,
+    ) -> SpecPaths {
+        let hash = self.hash(hasher);
+        let store = store.as_ref();
+        SpecPaths {
+            root: store.join("builds").join(hash.to_string()),
+            store: store.to_path_buf(),
+            hash,
+        }
+    }
+
+    pub fn dependencies(&self) -> impl Iterator<Item = Dependency> + '_ {
+        self.files
+            .iter()
+            .copied()
+            .map(Dependency::File)
+            .chain(self.dependencies.iter().cloned().map(Dependency::Output))
+    }
+}
+
+impl StableHash for Spec {
+    fn update<H: StableHasher>(&self, h: &mut H) {
+        h.update_hash(&self.name)
+            .update_hash(&self.outputs)
+            .update_hash(&self.files)
+            .update_hash(&self.dependencies)
+            .update_hash(&self.actions);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use nck_hashing::{StableHashExt, SupportedHasher};
+    use rstest::rstest;
+
+    use super::{Spec, SpecError};
+
+    const BOOTSTRAP: &str = r#"{
+        "name": "bootstrap-0.0.1",
+        "outputs": [ "out" ],
+        "files": [
+            "blake3-43bvwtdwfeaxy6hfq4mohwlwbl73pr52tntlidmdlpnl2uzmsqhq",
+            "blake3-7h32xiopodjandasbz7vefv4pfl7ble377xr7nfcoqiuzdcj4upq"
+        ],
+        "actions": [
+            { "action": "set", "name": "PATH", "value": "/bin:/tmp/busybox" },
+            { "action": "work_dir", "path": "/" },
+            {
+                "action": "copy",
+                "from": "/var/nck/store/files/blake3-43bvwtdwfeaxy6hfq4mohwlwbl73pr52tntlidmdlpnl2uzmsqhq",
+                "to": "/tmp/busybox/tar",
+                "executable": true
+            },
+            { "action": "exec", "path": "/tmp/busybox/tar", "args": [ "-xzf", "/tmp/rootfs.tar.gz" ] }
+        ]
+    }"#;
+
+    fn bootstrap() -> Spec {
+        serde_json::from_str(BOOTSTRAP).unwrap()
+    }
+
+    #[test]
+    fn parse_and_validate() {
+        let spec = bootstrap();
+        assert_eq!("bootstrap-0.0.1", spec.name().as_ref());
+        assert_eq!(2, spec.files().len());
+        assert_eq!(4, spec.actions().len());
+        spec.validate().unwrap();
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let spec = bootstrap();
+        let toml = toml::to_string_pretty(&spec).unwrap();
+        assert_eq!(spec, toml::from_str::<Spec>(&toml).unwrap());
+    }
+
+    #[test]
+    fn stable_hash() {
+        let spec = bootstrap();
+        let hash = spec.hash(SupportedHasher::blake3());
+        assert_eq!(hash, bootstrap().hash(SupportedHasher::blake3()));
+        assert_eq!(
+            "blake3-vzwd4cqbgopgfnhh4ooz3hm5iawbldy5tyl6igvxd7lv2osg33uq",
+            hash.to_string()
+        );
+    }
+
+    #[rstest]
+    #[case(r#"{ "name": "../etc", "outputs": ["out"] }"#)]
+    #[case(r#"{ "name": "", "outputs": ["out"] }"#)]
+    #[case(r#"{ "name": "pkg", "outputs": ["o/ut"] }"#)]
+    #[case(r#"{ "name": "pkg", "outputs": ["out"], "files": ["md5-abc"] }"#)]
+    #[case(r#"{ "name": "pkg", "outputs": ["out"], "actions": [{ "action": "unknown" }] }"#)]
+    #[case(r#"{ "name": "pkg", "outputs": ["out"], "dependencies": [{ "build": "blake3-43bvwtdwfeaxy6hfq4mohwlwbl73pr52tntlidmdlpnl2uzmsqhq", "output": "../out" }] }"#)]
+    fn rejects_malformed(#[case] json: &str) {
+        assert!(serde_json::from_str::<Spec>(json).is_err());
+    }
+
+    #[rstest]
+    #[case(r#"{ "name": "pkg", "outputs": [] }"#)]
+    #[case(r#"{ "name": "pkg", "outputs": ["out"], "actions": [{ "action": "set", "name": "A=B", "value": "" }] }"#)]
+    #[case(r#"{ "name": "pkg", "outputs": ["out"], "actions": [{ "action": "work_dir", "path": "relative" }] }"#)]
+    #[case(r#"{ "name": "pkg", "outputs": ["out"], "actions": [{ "action": "copy", "from": "/a", "to": "b" }] }"#)]
+    #[case(r#"{ "name": "pkg", "outputs": ["out"], "actions": [{ "action": "link", "from": "/a", "to": "/b", "executable": true }] }"#)]
+    #[case(
+        r#"{ "name": "pkg", "outputs": ["out"], "actions": [{ "action": "exec", "path": "" }] }"#
+    )]
+    fn rejects_invalid(#[case] json: &str) {
+        let spec: Spec = serde_json::from_str(json).unwrap();
+        assert!(matches!(
+            spec.validate(),
+            Err(SpecError::NoOutputs | SpecError::InvalidAction { .. })
+        ));
+    }
+}
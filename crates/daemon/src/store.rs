@@ -1,25 +1,68 @@
 use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::Permissions,
     hash::Hash,
+    io::SeekFrom,
     ops::{Deref, DerefMut},
+    os::unix::fs::PermissionsExt as _,
     path::{Path, PathBuf},
-    sync::{atomic::AtomicUsize, Arc},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize},
+        Arc,
+    },
+    task::{Context, Poll},
 };
 
+use bytes::Bytes;
 use dashmap::{mapref::entry::Entry, DashMap};
 use derive_more::{Deref, DerefMut};
+use futures::Stream;
 use nck_hashing::{SupportedHash, SupportedHasher};
-use nck_io::fs::TempFile;
-use tokio::{fs::File, io::AsyncWriteExt, task::JoinSet};
+use nck_io::fs::{move_replace, reflink_or_copy, TempFile};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt as _, AsyncSeek, AsyncWriteExt, ReadBuf},
+    net::unix::pipe,
+    sync::{watch, RwLock},
+    task::JoinSet,
+};
 
 use crate::{
-    build::linux::{Controller, Sandbox},
+    build::linux::{
+        ActionStatus, Controller, Sandbox, SandboxAction, SandboxConfig, SandboxResponse,
+        SupervisorId,
+    },
     settings::StoreSettings,
+    spec::{Dependency, OutputName, OutputReference, RootName, Spec, SpecPaths},
 };
 
+mod fetch;
+mod gc;
+mod metadata;
+mod output;
+mod roots;
+mod unpack;
+mod verify;
+
+pub use fetch::FetchError;
+pub use gc::GcReport;
+use metadata::BuildInfo;
+pub use metadata::{BuildRecord, FileRecord};
+pub use output::BuildOutput;
+use roots::Root;
+pub use unpack::UnpackError;
+pub use verify::VerifyReport;
+
+const LOG_CHUNK_SIZE: usize = 64 * 1024;
+
 #[derive(Debug)]
 pub struct TempStoreEntry {
     name: String,
     path: PathBuf,
+    /// Keeps the entry from being garbage collected.
+    _lock: DecrementLock,
 }
 
 impl TempStoreEntry {
@@ -30,6 +73,14 @@ impl TempStoreEntry {
     pub fn path(&self) -> &Path {
         &self.path
     }
+
+    /// Removes the directory and anything within it.
+    pub async fn remove(self) -> std::io::Result<()> {
+        match tokio::fs::remove_dir_all(&self.path).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            other => other,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -37,6 +88,9 @@ struct StorePaths {
     store: PathBuf,
     files: PathBuf,
     temp: PathBuf,
+    roots: PathBuf,
+    builds: PathBuf,
+    file_records: PathBuf,
 }
 
 impl StorePaths {
@@ -45,11 +99,21 @@ impl StorePaths {
             store: settings.path.clone(),
             files: settings.path.join("files"),
             temp: settings.path.join("temp"),
+            roots: settings.path.join("roots"),
+            builds: settings.path.join("builds"),
+            file_records: settings.path.join("metadata").join("files"),
         };
 
-        tokio::fs::create_dir_all(result.store.clone()).await?;
-        tokio::fs::create_dir_all(result.files.clone()).await?;
-        tokio::fs::create_dir_all(result.temp.clone()).await?;
+        let mut js = JoinSet::new();
+        for path in [
+            &result.files,
+            &result.temp,
+            &result.roots,
+            &result.builds,
+            &result.file_records,
+        ] {
+            js.spawn(tokio::fs::create_dir_all(path.clone()));
+        }
 
         while let Some(awaited) = js.join_next().await {
             match awaited {
@@ -59,6 +123,17 @@ impl StorePaths {
             }
         }
 
+        // Nothing can be using temporary entries yet, so anything there was left behind by a previous daemon.
+        let temp = result.temp.clone();
+        match tokio::task::spawn_blocking(move || gc::clear(&temp)).await? {
+            Ok((0, _)) => {}
+            Ok((entries, bytes)) => {
+                tracing::info!(entries, bytes, "removed leftover temporary entries")
+            }
+            // Whatever is left is only wasted space, which garbage collection can reclaim later.
+            Err(error) => tracing::warn!(?error, "failed to remove leftover temporary entries"),
+        }
+
         Ok(result)
     }
 }
@@ -67,12 +142,34 @@ impl StorePaths {
 pub struct StoreState {
     controller: Controller,
     locks: DashMap<PathBuf, AtomicUsize>,
-    builds: DashMap<usize, Build>,
-    counter: AtomicUsize,
+    builds: DashMap<SupportedHash, Build>,
+    files: DashMap<SupportedHash, FileRecord>,
+    roots: DashMap<RootName, Root>,
     paths: StorePaths,
+    /// Files that are never garbage collected.
+    pinned: Vec<SupportedHash>,
+    allow_file_urls: bool,
+    /// Held for writing while garbage is collected.
+    collecting: RwLock<()>,
+    /// The last generation given to a build.
+    generations: AtomicU64,
 }
 
 impl StoreState {
+    /// A generation for a new build, which no earlier build has had.
+    fn next_generation(&self) -> u64 {
+        self.generations
+            .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+            + 1
+    }
+
+    /// Whether the build for a spec is still the one with the given generation, rather than a later restart.
+    fn is_current(&self, hash: &SupportedHash, generation: u64) -> bool {
+        self.builds
+            .get(hash)
+            .is_some_and(|build| build.generation == generation)
+    }
+
     fn increase_lock(&self, path: PathBuf) {
         self.locks
             .entry(path.clone())
@@ -106,13 +203,21 @@ pub struct Store(Arc<StoreState>);
 
 impl Store {
     pub async fn new(controller: Controller, settings: &StoreSettings) -> anyhow::Result<Self> {
-        Ok(Self(Arc::new(StoreState {
+        let store = Self(Arc::new(StoreState {
             controller,
             locks: DashMap::new(),
             builds: DashMap::new(),
-            counter: AtomicUsize::new(0),
+            files: DashMap::new(),
+            roots: DashMap::new(),
             paths: StorePaths::new(settings).await?,
-        })))
+            pinned: settings.gc.pinned.clone(),
+            allow_file_urls: settings.fetch.allow_file_urls,
+            collecting: RwLock::new(()),
+            generations: AtomicU64::new(0),
+        }));
+        store.load_roots().await?;
+        store.load_metadata().await?;
+        Ok(store)
     }
 
     pub async fn get_file(&self, hash: &SupportedHash) -> std::io::Result<StoreLock> {
@@ -125,54 +230,357 @@ impl Store {
             .truncate(false)
             .open(path.as_path())
             .await?;
+        self.touch_file(hash, file.metadata().await?.len()).await;
         Ok(StoreLock { file, dec })
     }
 
+    /// Returns the hashes that are not in `files/`, in the order they were given.
+    pub async fn missing(&self, hashes: &[SupportedHash]) -> std::io::Result<Vec<SupportedHash>> {
+        let mut seen = BTreeSet::new();
+        let mut missing = Vec::new();
+        for hash in hashes {
+            if !seen.insert(*hash) {
+                continue;
+            }
+            let path = self.paths.files.join(hash.to_string());
+            if !tokio::fs::try_exists(&path).await? {
+                missing.push(*hash);
+            }
+        }
+        Ok(missing)
+    }
+
     pub async fn create_file(&self) -> std::io::Result<PendingFile> {
         PendingFile::new(self.0.clone()).await
     }
 
-    pub async fn start(&self, spec: AsRef<Path>) -> anyhow::Result<()> {
-        let spec = spec.as_ref();
-        let output_path = spec
-            .paths(&self.paths.store, SupportedHasher::blake3())
-            .spec();
+    /// Starts building the spec, unless a build for it is already queued, running or has succeeded.
+    pub async fn start(&self, spec: Spec) -> anyhow::Result<SupportedHash> {
+        let paths = spec.paths(&self.paths.store, SupportedHasher::blake3());
+        let hash = *paths.hash();
+
+        let (progress, log) = watch::channel(LogProgress::default());
+        let generation = self.next_generation();
+        let build = Build {
+            status: BuildStatus::Queued,
+            generation,
+            locks: Vec::new(),
+            supervisor: None,
+            log: BuildLog {
+                path: paths.log(),
+                progress: log,
+            },
+            info: BuildInfo {
+                name: spec.name().clone(),
+                files: spec.files().clone(),
+                dependencies: spec.output_dependencies().clone(),
+                started: metadata::now(),
+                finished: None,
+            },
+        };
+        match self.builds.entry(hash) {
+            Entry::Occupied(occupied) if !occupied.get().status.can_restart() => {
+                tracing::debug!(%hash, "build already started");
+                return Ok(hash);
+            }
+            Entry::Occupied(mut occupied) => {
+                occupied.insert(build);
+            }
+            Entry::Vacant(vacant) => {
+                vacant.insert(build);
+            }
+        }
+        self.save_build(&hash).await;
+
+        let sandbox = match self.spawn_build(&spec, &paths, generation, progress).await {
+            Ok(sandbox) => sandbox,
+            Err(error) => {
+                self.finish(&hash, generation, BuildStatus::failed(format!("{error:#}")))
+                    .await;
+                return Err(error);
+            }
+        };
+
+        let outputs: BTreeMap<_, _> = spec
+            .outputs()
+            .iter()
+            .map(|output| (output.clone(), paths.output(output)))
+            .collect();
+        let output_paths: Vec<_> = outputs.values().cloned().collect();
+        let store = self.clone();
+        tokio::spawn(async move {
+            let status = match store.wait(&hash, sandbox).await {
+                Ok(()) => {
+                    // Captured files are only referenced once the build has finished.
+                    let _collecting = store.collecting.read().await;
+                    if store.is_current(&hash, generation) {
+                        let status = store.capture(&hash, outputs).await;
+                        store.finish(&hash, generation, status).await
+                    } else {
+                        None
+                    }
+                }
+                Err(status) => store.finish(&hash, generation, status).await,
+            };
+            // The outputs belong to the build that restarted this one.
+            let Some(status) = status else {
+                tracing::debug!(%hash, generation, "build was restarted");
+                return;
+            };
+            if !status.is_succeeded() {
+                for output in output_paths {
+                    if let Err(error) = remove_output(&output).await {
+                        tracing::warn!(%hash, ?output, ?error, "failed to remove a build output");
+                    }
+                }
+            }
+        });
+        Ok(hash)
+    }
+
+    /// The status of the build for a spec.
+    pub fn status(&self, hash: &SupportedHash) -> Option<BuildStatus> {
+        self.builds.get(hash).map(|build| build.status.clone())
+    }
+
+    /// An output of a successful build.
+    pub fn output(&self, reference: &OutputReference) -> Option<BuildOutput> {
+        match &self.builds.get(&reference.build)?.status {
+            BuildStatus::Succeeded { outputs } => outputs.get(&reference.output).cloned(),
+            _ => None,
+        }
+    }
+
+    /// The output of the build for a spec, which may still be running.
+    pub fn log(&self, hash: &SupportedHash) -> Option<BuildLog> {
+        self.builds.get(hash).map(|build| build.log.clone())
+    }
+
+    async fn spawn_build(
+        &self,
+        spec: &Spec,
+        paths: &SpecPaths,
+        generation: u64,
+        progress: watch::Sender<LogProgress>,
+    ) -> anyhow::Result<Sandbox> {
+        let hash = paths.hash();
+        let output_path = paths.spec();
 
         let mut locks = Vec::new();
         let dec = DecrementLock::new(output_path.clone(), self.0.clone());
         locks.push(dec);
 
         {
-            let parent = output_path.parent().unwrap();
-            let (temp, mut file) = TempFile::new_in(parent).await?;
-            let s = toml::to_string_pretty(&spec)?;
+            tokio::fs::create_dir_all(paths.root()).await?;
+            let (temp, mut file) = TempFile::new_in(paths.root()).await?;
+            let s = toml::to_string_pretty(spec)?;
             file.write_all(s.as_bytes()).await?;
             temp.write_to(output_path.as_path()).await?;
         }
 
+        let mut files = Vec::new();
         for dep in spec.dependencies() {
+            if let Dependency::Output(reference) = &dep {
+                if self.output(reference).is_none() {
+                    anyhow::bail!(
+                        "output {} of {} has not been built",
+                        reference.output,
+                        reference.build
+                    );
+                }
+            }
+
             let path = dep.path(&self.paths.store);
             let dec = DecrementLock::new(path.clone(), self.0.clone());
             locks.push(dec);
+            if let Dependency::File(hash) = &dep {
+                self.touch_file(hash, tokio::fs::metadata(&path).await?.len())
+                    .await;
+            }
+            files.push(path);
         }
 
-        let sandbox = self.controller.spawn_async().await?;
-        let build = Build { sandbox, locks };
+        if let Some(mut build) = self.builds.get_mut(hash) {
+            if build.generation == generation {
+                build.locks = locks;
+            }
+        }
+
+        // The build runs as the root user of the sandbox, which can only write to outputs that it owns.
+        let (uid, gid) = self.controller.sandbox_owner().await;
+        let mut outputs = Vec::new();
+        let mut actions = Vec::new();
+        for name in spec.outputs() {
+            let path = paths.output(name);
+            remove_output(&path).await?;
+            tokio::fs::create_dir_all(&path).await?;
+            tokio::fs::set_permissions(&path, Permissions::from_mode(0o755)).await?;
+            nix::unistd::chown(&path, Some(uid), Some(gid))?;
+            actions.push(SandboxAction::Set {
+                name: name.to_string(),
+                value: path.to_string_lossy().into_owned(),
+            });
+            outputs.push(path);
+        }
+        actions.extend(spec.actions().iter().map(SandboxAction::from));
+
+        let log_file = File::create(paths.log()).await?;
+
+        let config = SandboxConfig::new(paths.root().to_path_buf());
+        let sandbox = self.controller.spawn_async(config).await?;
+        let log = sandbox.run(files, outputs, actions).await?;
+
+        let hash = *hash;
+        tokio::spawn(async move {
+            if let Err(error) = record_log(log, log_file, &progress).await {
+                tracing::warn!(%hash, ?error, "failed to record the build log");
+            }
+            progress.send_modify(|progress| progress.finished = true);
+        });
+
+        let cancelled = match self.builds.get_mut(&hash) {
+            Some(mut build)
+                if build.generation == generation && build.status == BuildStatus::Queued =>
+            {
+                build.status = BuildStatus::Running;
+                build.supervisor = Some(sandbox.supervisor());
+                false
+            }
+            _ => true,
+        };
+        if cancelled {
+            self.controller.cancel(sandbox.supervisor()).await?;
+            anyhow::bail!("the build was cancelled before it started");
+        }
+
+        tracing::info!(%hash, "build started");
+        Ok(sandbox)
+    }
+
+    /// Waits for the sandbox to finish, returning the failure if the build did not succeed.
+    async fn wait(&self, hash: &SupportedHash, sandbox: Sandbox) -> Result<(), BuildStatus> {
+        loop {
+            let response = match sandbox.next_response().await {
+                Ok(response) => response,
+                Err(error) => {
+                    tracing::error!(%hash, ?error, "lost contact with the sandbox");
+                    return Err(BuildStatus::failed("lost contact with the sandbox"));
+                }
+            };
+
+            match response {
+                SandboxResponse::Action {
+                    index,
+                    status: ActionStatus::Success,
+                } => tracing::debug!(%hash, index, "action completed"),
+                SandboxResponse::Action { index, status } => {
+                    tracing::warn!(%hash, index, ?status, "action failed");
+                    return Err(BuildStatus::action_failed(index, status));
+                }
+                SandboxResponse::SetupFailed(reason) => {
+                    tracing::error!(%hash, reason, "failed to prepare the sandbox");
+                    return Err(BuildStatus::failed(reason));
+                }
+                SandboxResponse::Finished => return Ok(()),
+            }
+        }
+    }
 
-        let id = self
-            .counter
-            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-        self.builds.insert(id, build);
-        Ok(())
+    /// Captures the outputs of a finished build.
+    async fn capture(
+        &self,
+        hash: &SupportedHash,
+        outputs: BTreeMap<OutputName, PathBuf>,
+    ) -> BuildStatus {
+        let mut captured = BTreeMap::new();
+        for (name, path) in outputs {
+            match output::capture(self, &path).await {
+                Ok(output) => {
+                    tracing::debug!(%hash, %name, manifest = %output.manifest, "captured output");
+                    captured.insert(name, output);
+                }
+                Err(error) => {
+                    tracing::error!(%hash, %name, ?error, "failed to capture output");
+                    return BuildStatus::failed(format!(
+                        "failed to capture output {name}: {error:#}"
+                    ));
+                }
+            }
+        }
+
+        tracing::info!(%hash, "build succeeded");
+        BuildStatus::Succeeded { outputs: captured }
+    }
+
+    /// Records the final status of a build, unless it was cancelled, and releases its locks.
+    ///
+    /// Returns the status that was recorded, or `None` if the build has since been restarted, which is left alone.
+    async fn finish(
+        &self,
+        hash: &SupportedHash,
+        generation: u64,
+        status: BuildStatus,
+    ) -> Option<BuildStatus> {
+        let status = {
+            let mut build = self.builds.get_mut(hash)?;
+            if build.generation != generation {
+                return None;
+            }
+            if build.status != BuildStatus::Cancelled {
+                build.status = status;
+                build.info.finished = Some(metadata::now());
+            }
+            build.supervisor = None;
+            build.locks.clear();
+            build.status.clone()
+        };
+        self.save_build(hash).await;
+        Some(status)
+    }
+
+    /// Cancels a queued or running build, stopping its sandbox and releasing its locks.
+    ///
+    /// Returns the status of the build before it was cancelled, which is left unchanged if the build has already
+    /// finished.
+    pub async fn cancel(&self, hash: &SupportedHash) -> anyhow::Result<Option<BuildStatus>> {
+        let (previous, supervisor) = {
+            let Some(mut build) = self.builds.get_mut(hash) else {
+                return Ok(None);
+            };
+            let previous = build.status.clone();
+            if !matches!(previous, BuildStatus::Queued | BuildStatus::Running) {
+                return Ok(Some(previous));
+            }
+            build.status = BuildStatus::Cancelled;
+            build.info.finished = Some(metadata::now());
+            build.locks.clear();
+            (previous, build.supervisor.take())
+        };
+        self.save_build(hash).await;
+
+        if let Some(supervisor) = supervisor {
+            let running = self.controller.cancel(supervisor).await?;
+            tracing::info!(%hash, running, "build cancelled");
+        } else {
+            tracing::info!(%hash, "build cancelled before it started");
+        }
+        Ok(Some(previous))
     }
 
     pub async fn create_temporary_entry(&self) -> anyhow::Result<TempStoreEntry> {
         loop {
             let pet = petname::petname(3, "-");
             let path = self.paths.temp.join(&pet);
+            let lock = DecrementLock::new(path.clone(), self.0.clone());
 
             match tokio::fs::create_dir(&path).await {
-                Ok(()) => return Ok(TempStoreEntry { name: pet, path }),
+                Ok(()) => {
+                    return Ok(TempStoreEntry {
+                        name: pet,
+                        path,
+                        _lock: lock,
+                    })
+                }
                 Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
                 Err(e) => Err(e)?,
             }
@@ -180,6 +588,18 @@ impl Store {
     }
 }
 
+#[cfg(test)]
+impl Store {
+    /// Opens a store at `path`, with a controller that does not start sandboxes.
+    pub async fn for_test(path: &Path) -> Self {
+        let settings = StoreSettings {
+            path: path.to_path_buf(),
+            ..Default::default()
+        };
+        Store::new(Controller::fake(), &settings).await.unwrap()
+    }
+}
+
 /// Decreases the refcount for a store file lock when dropped.
 #[derive(Debug)]
 struct DecrementLock {
@@ -234,10 +654,38 @@ impl StoreLock {
     }
 }
 
+impl Deref for StoreLock {
+    type Target = File;
+
+    fn deref(&self) -> &Self::Target {
+        &self.file
+    }
+}
+
+impl AsyncRead for StoreLock {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.file).poll_read(cx, buf)
+    }
+}
+
+impl AsyncSeek for StoreLock {
+    fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        Pin::new(&mut self.file).start_seek(position)
+    }
+
+    fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        Pin::new(&mut self.file).poll_complete(cx)
+    }
+}
+
 /// A file that will be written to the store.
 #[derive(Debug)]
 pub struct PendingFile {
-    _temp: TempFile,
+    temp: TempFile,
     lock: StoreLock,
     state: Arc<StoreState>,
 }
@@ -253,19 +701,46 @@ impl PendingFile {
             file,
             dec: dec.unwrap(),
         };
-        Ok(Self {
-            _temp: temp,
-            lock,
-            state,
-        })
+        Ok(Self { temp, lock, state })
     }
 
-    /// Writes the file into the store.
+    /// Writes the file into the store, or discards it if the store already has a file with the same hash.
+    ///
+    /// The file is moved into place when the temporary directory is on the same filesystem as the store, and is
+    /// otherwise reflinked or copied.
     pub async fn complete(self, hash: &SupportedHash) -> std::io::Result<StoreLock> {
-        let path = self.state.paths.files.join(hash.to_string());
+        let PendingFile {
+            temp,
+            lock: _lock,
+            state,
+        } = self;
+        let path = state.paths.files.join(hash.to_string());
 
-        let dec = DecrementLock::new(path.clone(), self.state.clone());
-        tokio::fs::copy(self.lock.as_path(), path.as_path()).await?;
+        let dec = DecrementLock::new(path.clone(), state.clone());
+        if tokio::fs::try_exists(&path).await? {
+            tracing::trace!(%hash, "discarding duplicate file");
+        } else {
+            // Store files are bound into sandboxes, where the daemon user is not mapped.
+            tokio::fs::set_permissions(temp.as_path(), Permissions::from_mode(0o444)).await?;
+            match move_replace(temp.as_path(), &path).await {
+                Ok(()) => {
+                    temp.forget();
+                }
+                Err(e) if e.raw_os_error() == Some(nix::libc::EXDEV) => {
+                    tracing::trace!(%hash, "copying file across filesystems");
+                    let mut dec = None;
+                    let (copy, _) = TempFile::new_with_side_effect_in(&state.paths.files, |path| {
+                        dec = Some(DecrementLock::new(path.to_path_buf(), state.clone()))
+                    })
+                    .await?;
+                    reflink_or_copy(temp.as_path(), copy.as_path()).await?;
+                    tokio::fs::set_permissions(copy.as_path(), Permissions::from_mode(0o444))
+                        .await?;
+                    copy.write_to(&path).await?;
+                }
+                Err(e) => return Err(e),
+            }
+        }
 
         let file = tokio::fs::OpenOptions::new()
             .read(true)
@@ -273,6 +748,7 @@ impl PendingFile {
             .truncate(false)
             .open(path.as_path())
             .await?;
+        state.touch_file(hash, file.metadata().await?.len()).await;
 
         Ok(StoreLock { file, dec })
     }
@@ -292,8 +768,326 @@ impl DerefMut for PendingFile {
     }
 }
 
+/// The progress of a build.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum BuildStatus {
+    #[default]
+    Queued,
+    Running,
+    Succeeded {
+        outputs: BTreeMap<OutputName, BuildOutput>,
+    },
+    Failed {
+        /// The index of the action that failed.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        action: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        exit_code: Option<i32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        signal: Option<i32>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    },
+    Cancelled,
+}
+
+impl BuildStatus {
+    fn failed(message: impl Into<String>) -> Self {
+        BuildStatus::Failed {
+            action: None,
+            exit_code: None,
+            signal: None,
+            message: Some(message.into()),
+        }
+    }
+
+    fn action_failed(index: usize, status: ActionStatus) -> Self {
+        let (exit_code, signal, message) = match status {
+            ActionStatus::Success => (None, None, None),
+            ActionStatus::Exited(code) => (Some(code), None, None),
+            ActionStatus::Signaled(signal) => (None, Some(signal), None),
+            ActionStatus::Failed(message) => (None, None, Some(message)),
+        };
+        BuildStatus::Failed {
+            action: Some(index),
+            exit_code,
+            signal,
+            message,
+        }
+    }
+
+    pub fn is_failed(&self) -> bool {
+        matches!(self, BuildStatus::Failed { .. })
+    }
+
+    pub fn is_succeeded(&self) -> bool {
+        matches!(self, BuildStatus::Succeeded { .. })
+    }
+
+    /// Whether starting the spec again should start a new build.
+    fn can_restart(&self) -> bool {
+        matches!(self, BuildStatus::Failed { .. } | BuildStatus::Cancelled)
+    }
+}
+
 #[derive(Debug)]
 pub struct Build {
-    sandbox: Sandbox,
+    status: BuildStatus,
+    /// Tells this attempt at the build apart from earlier ones for the same spec, whose tasks may still be running.
+    generation: u64,
     locks: Vec<DecrementLock>,
+    /// The supervisor of the sandbox, while the build is running.
+    supervisor: Option<SupervisorId>,
+    log: BuildLog,
+    info: BuildInfo,
+}
+
+/// Removes a build output, including any read-only directories within it.
+async fn remove_output(path: &Path) -> std::io::Result<()> {
+    match tokio::fs::remove_dir_all(path).await {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            let path = path.to_path_buf();
+            tokio::task::spawn_blocking(move || {
+                make_writable(&path)?;
+                std::fs::remove_dir_all(&path)
+            })
+            .await?
+        }
+        other => other,
+    }
+}
+
+fn make_writable(path: &Path) -> std::io::Result<()> {
+    let stat = std::fs::symlink_metadata(path)?;
+    if stat.is_dir() {
+        std::fs::set_permissions(path, Permissions::from_mode(0o755))?;
+        for entry in std::fs::read_dir(path)? {
+            make_writable(&entry?.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// How much of a build log has been written.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LogProgress {
+    len: u64,
+    finished: bool,
+}
+
+/// Copies the output of a sandbox into the log file.
+async fn record_log(
+    mut log: pipe::Receiver,
+    mut file: File,
+    progress: &watch::Sender<LogProgress>,
+) -> std::io::Result<()> {
+    let mut buf = vec![0u8; LOG_CHUNK_SIZE];
+    loop {
+        let n = log.read(&mut buf).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        file.write_all(&buf[..n]).await?;
+        file.flush().await?;
+        progress.send_modify(|progress| progress.len += n as u64);
+    }
+}
+
+/// A build log that can be followed while the build runs.
+#[derive(Debug, Clone)]
+pub struct BuildLog {
+    path: PathBuf,
+    progress: watch::Receiver<LogProgress>,
+}
+
+impl BuildLog {
+    /// Streams the log from the start, ending once the build has finished.
+    pub fn stream(self) -> impl Stream<Item = std::io::Result<Bytes>> {
+        futures::stream::try_unfold((self, None, 0u64), |(mut log, file, position)| async move {
+            loop {
+                let LogProgress { len, finished } = *log.progress.borrow_and_update();
+
+                if position < len {
+                    let mut file = match file {
+                        Some(file) => file,
+                        None => File::open(&log.path).await?,
+                    };
+                    let mut buf = vec![0u8; (len - position).min(LOG_CHUNK_SIZE as u64) as usize];
+                    let n = file.read(&mut buf).await?;
+                    if n == 0 {
+                        return Ok(None);
+                    }
+                    buf.truncate(n);
+                    let position = position + n as u64;
+                    return Ok(Some((Bytes::from(buf), (log, Some(file), position))));
+                }
+
+                if finished || log.progress.changed().await.is_err() {
+                    return Ok(None);
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        os::unix::fs::{MetadataExt as _, PermissionsExt as _},
+        time::Duration,
+    };
+
+    use futures::StreamExt;
+    use nck_io::fs::TempDir;
+    use tokio::sync::watch;
+
+    use crate::{
+        build::linux::{ActionStatus, Controller},
+        settings::StoreSettings,
+        spec::Spec,
+    };
+
+    use super::{BuildLog, BuildStatus, LogProgress, Store};
+
+    #[test]
+    fn status_json() {
+        let status = BuildStatus::action_failed(2, ActionStatus::Exited(1));
+        assert_eq!(
+            r#"{"status":"failed","action":2,"exit_code":1}"#,
+            serde_json::to_string(&status).unwrap()
+        );
+        assert_eq!(
+            r#"{"status":"queued"}"#,
+            serde_json::to_string(&BuildStatus::Queued).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn follow_log() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.as_path().join("log");
+        let (progress, receiver) = watch::channel(LogProgress::default());
+        let mut stream = Box::pin(
+            BuildLog {
+                path: path.clone(),
+                progress: receiver,
+            }
+            .stream(),
+        );
+
+        std::fs::write(&path, "hello ").unwrap();
+        progress.send_modify(|progress| progress.len = 6);
+        assert_eq!(&b"hello "[..], stream.next().await.unwrap().unwrap());
+
+        std::fs::write(&path, "hello world").unwrap();
+        progress.send_modify(|progress| {
+            progress.len = 11;
+            progress.finished = true;
+        });
+        assert_eq!(&b"world"[..], stream.next().await.unwrap().unwrap());
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn start_prepares_outputs() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::for_test(dir.as_path()).await;
+        let spec: Spec = serde_json::from_str(r#"{ "name": "pkg", "outputs": ["out"] }"#).unwrap();
+
+        let hash = store.start(spec).await.unwrap();
+        assert_eq!(Some(BuildStatus::Running), store.status(&hash));
+
+        let output = dir.as_path().join(format!("outputs/{hash}-out"));
+        let stat = std::fs::metadata(output).unwrap();
+        let (uid, gid) = store.controller.sandbox_owner().await;
+        assert_eq!((uid.as_raw(), gid.as_raw()), (stat.uid(), stat.gid()));
+        assert_eq!(0o755, stat.permissions().mode() & 0o777);
+    }
+
+    #[tokio::test]
+    async fn cancel_running_build() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::for_test(dir.as_path()).await;
+        let spec: Spec = serde_json::from_str(r#"{ "name": "pkg", "outputs": ["out"] }"#).unwrap();
+        let hash = store.start(spec).await.unwrap();
+        assert_eq!(Some(BuildStatus::Running), store.status(&hash));
+
+        assert_eq!(
+            Some(BuildStatus::Running),
+            store.cancel(&hash).await.unwrap()
+        );
+        assert_eq!(Some(BuildStatus::Cancelled), store.status(&hash));
+
+        // The build stops waiting for the sandbox once it loses contact with it, and removes the output.
+        let output = dir.as_path().join(format!("outputs/{hash}-out"));
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while output.exists() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(Some(BuildStatus::Cancelled), store.status(&hash));
+        assert_eq!(
+            Some(BuildStatus::Cancelled),
+            store.cancel(&hash).await.unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn restart_after_cancel() {
+        let dir = TempDir::new().unwrap();
+        let (controller, held) = Controller::fake_holding();
+        let settings = StoreSettings {
+            path: dir.as_path().to_path_buf(),
+            ..Default::default()
+        };
+        let store = Store::new(controller, &settings).await.unwrap();
+        let spec: Spec = serde_json::from_str(r#"{ "name": "pkg", "outputs": ["out"] }"#).unwrap();
+        let hash = store.start(spec.clone()).await.unwrap();
+        store.cancel(&hash).await.unwrap();
+        assert_eq!(hash, store.start(spec).await.unwrap());
+        assert_eq!(Some(BuildStatus::Running), store.status(&hash));
+
+        // The cancelled build loses contact with its sandbox, but leaves the restarted build alone.
+        held.release();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(Some(BuildStatus::Running), store.status(&hash));
+        assert!(dir.as_path().join(format!("outputs/{hash}-out")).exists());
+    }
+
+    #[tokio::test]
+    async fn restart_recovers() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::for_test(dir.as_path()).await;
+        let spec: Spec = serde_json::from_str(r#"{ "name": "pkg", "outputs": ["out"] }"#).unwrap();
+        let hash = store.start(spec).await.unwrap();
+        let output = dir.as_path().join(format!("outputs/{hash}-out"));
+        assert!(output.exists());
+        std::fs::create_dir_all(dir.as_path().join("temp/upload/nested")).unwrap();
+        std::fs::write(dir.as_path().join("temp/file"), "partial").unwrap();
+
+        // The daemon stops without the build finishing.
+        let store = Store::for_test(dir.as_path()).await;
+        assert_eq!(
+            Some(BuildStatus::failed("interrupted by a daemon restart")),
+            store.status(&hash)
+        );
+        assert!(!output.exists());
+        assert_eq!(
+            0,
+            std::fs::read_dir(dir.as_path().join("temp"))
+                .unwrap()
+                .count()
+        );
+
+        // The recovered status is saved, so it survives another restart.
+        let store = Store::for_test(dir.as_path()).await;
+        assert_eq!(
+            Some(BuildStatus::failed("interrupted by a daemon restart")),
+            store.status(&hash)
+        );
+    }
 }
@@ -0,0 +1,565 @@
+use std::{
+    io::ErrorKind,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use http_body_util::{BodyExt as _, Empty};
+use hyper::{client::conn::http1::SendRequest, header, Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use nck_hashing::{SupportedHash, SupportedHasher};
+use thiserror::Error;
+use tokio::{
+    fs::File,
+    io::{AsyncRead, AsyncReadExt as _, AsyncSeekExt as _, AsyncWrite, AsyncWriteExt as _},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    rustls::{pki_types::ServerName, ClientConfig, RootCertStore},
+    TlsConnector,
+};
+use url::{Position, Url};
+
+use super::{Store, StoreLock};
+
+/// How many times a download is attempted before giving up.
+const MAX_ATTEMPTS: u32 = 5;
+/// How long to wait before the first retry, which doubles with each attempt.
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_REDIRECTS: usize = 10;
+const USER_AGENT: &str = concat!("nck-daemon/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("unsupported URL scheme {0}")]
+    UnsupportedScheme(String),
+    #[error("file URLs are disabled, set store.fetch.allow_file_urls to enable them")]
+    FileUrlsDisabled,
+    #[error("invalid URL {0}")]
+    InvalidUrl(Url),
+    #[error("the server responded with {0}")]
+    Status(StatusCode),
+    #[error("too many redirects")]
+    TooManyRedirects,
+    #[error("the server resumed the download at the wrong offset")]
+    UnexpectedRange,
+    #[error("expected {expected}, but the download is {actual}")]
+    Mismatch {
+        expected: SupportedHash,
+        actual: SupportedHash,
+    },
+    #[error("failed to connect")]
+    Connect(#[source] std::io::Error),
+    #[error("failed to establish a secure connection")]
+    Tls(#[source] std::io::Error),
+    #[error("failed to transfer")]
+    Transfer(#[from] hyper::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl FetchError {
+    /// Whether trying again might succeed.
+    fn is_transient(&self) -> bool {
+        match self {
+            Self::Status(status) => {
+                status.is_server_error()
+                    || *status == StatusCode::REQUEST_TIMEOUT
+                    || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            Self::UnexpectedRange | Self::Connect(_) | Self::Transfer(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Store {
+    /// Downloads a file into the store, unless the store already has it.
+    pub async fn fetch(
+        &self,
+        url: &Url,
+        expected: &SupportedHash,
+    ) -> Result<StoreLock, FetchError> {
+        // Any client could otherwise read whatever the daemon can.
+        if url.scheme() == "file" && !self.allow_file_urls {
+            return Err(FetchError::FileUrlsDisabled);
+        }
+
+        match self.get_file(expected).await {
+            Ok(lock) => {
+                tracing::debug!(%url, %expected, "file already cached");
+                return Ok(lock);
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let mut file = self.create_file().await?;
+        download(url, expected, &mut file, tls_config()).await?;
+        let lock = file.complete(expected).await?;
+        tracing::info!(%url, %expected, "file fetched");
+        Ok(lock)
+    }
+}
+
+/// The TLS configuration for downloads, which trusts the Mozilla root certificates.
+fn tls_config() -> Arc<ClientConfig> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    CONFIG
+        .get_or_init(|| {
+            let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            let mut config = ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            config.alpn_protocols = vec![b"http/1.1".to_vec()];
+            Arc::new(config)
+        })
+        .clone()
+}
+
+/// Downloads a URL into a file, retrying and resuming as needed, and verifies its hash.
+async fn download(
+    url: &Url,
+    expected: &SupportedHash,
+    file: &mut File,
+    tls: Arc<ClientConfig>,
+) -> Result<(), FetchError> {
+    let mut download = Download {
+        file,
+        tls,
+        expected: *expected,
+        hasher: expected.create_matching_hasher(),
+        written: 0,
+    };
+
+    let mut attempt = 1;
+    loop {
+        let result = match url.scheme() {
+            "file" => download.copy(url).await,
+            "http" | "https" => download.request(url).await,
+            other => return Err(FetchError::UnsupportedScheme(other.to_string())),
+        };
+        download.file.flush().await?;
+        match result {
+            Ok(()) => break,
+            Err(error) if attempt < MAX_ATTEMPTS && error.is_transient() => {
+                let delay = RETRY_DELAY * 2u32.pow(attempt - 1);
+                tracing::warn!(%url, ?error, attempt, ?delay, "download failed, retrying");
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    let actual = download.hasher.finalize();
+    if actual != *expected {
+        return Err(FetchError::Mismatch {
+            expected: *expected,
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// The progress of a download, which is kept between attempts so that they can resume.
+struct Download<'a> {
+    file: &'a mut File,
+    tls: Arc<ClientConfig>,
+    expected: SupportedHash,
+    hasher: SupportedHasher,
+    /// How much of the file has been written and hashed.
+    written: u64,
+}
+
+impl Download<'_> {
+    async fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.hasher.update(data);
+        self.file.write_all(data).await?;
+        self.written += data.len() as u64;
+        Ok(())
+    }
+
+    /// Discards everything that has been downloaded so far.
+    async fn restart(&mut self) -> std::io::Result<()> {
+        self.file.flush().await?;
+        self.file.set_len(0).await?;
+        self.file.seek(std::io::SeekFrom::Start(0)).await?;
+        self.hasher = self.expected.create_matching_hasher();
+        self.written = 0;
+        Ok(())
+    }
+
+    async fn copy(&mut self, url: &Url) -> Result<(), FetchError> {
+        let path = url
+            .to_file_path()
+            .map_err(|_| FetchError::InvalidUrl(url.clone()))?;
+        let mut source = File::open(path).await?;
+        source.seek(std::io::SeekFrom::Start(self.written)).await?;
+
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let len = source.read(&mut buffer).await?;
+            if len == 0 {
+                return Ok(());
+            }
+            self.write(&buffer[..len]).await?;
+        }
+    }
+
+    async fn request(&mut self, url: &Url) -> Result<(), FetchError> {
+        let mut url = url.clone();
+        for _ in 0..MAX_REDIRECTS {
+            match self.request_once(&url).await? {
+                Some(location) => {
+                    tracing::debug!(from = %url, to = %location, "following redirect");
+                    url = location;
+                }
+                None => return Ok(()),
+            }
+        }
+        Err(FetchError::TooManyRedirects)
+    }
+
+    /// Requests the rest of the URL, returning where to go instead if it redirects.
+    async fn request_once(&mut self, url: &Url) -> Result<Option<Url>, FetchError> {
+        let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+            return Err(FetchError::InvalidUrl(url.clone()));
+        };
+
+        let stream = TcpStream::connect(format!("{host}:{port}"))
+            .await
+            .map_err(FetchError::Connect)?;
+        let mut sender = match url.scheme() {
+            "http" => handshake(stream).await?,
+            "https" => {
+                // IPv6 hosts are bracketed in URLs, but not in certificates.
+                let name = ServerName::try_from(host.trim_matches(['[', ']']).to_string())
+                    .map_err(|_| FetchError::InvalidUrl(url.clone()))?;
+                let stream = TlsConnector::from(self.tls.clone())
+                    .connect(name, stream)
+                    .await
+                    .map_err(FetchError::Tls)?;
+                handshake(stream).await?
+            }
+            other => return Err(FetchError::UnsupportedScheme(other.to_string())),
+        };
+
+        let host = match url.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_string(),
+        };
+        let mut request = Request::get(&url[Position::BeforePath..Position::AfterQuery])
+            .header(header::HOST, host)
+            .header(header::USER_AGENT, USER_AGENT);
+        if self.written != 0 {
+            request = request.header(header::RANGE, format!("bytes={}-", self.written));
+        }
+        let request = request
+            .body(Empty::<Bytes>::new())
+            .map_err(|_| FetchError::InvalidUrl(url.clone()))?;
+        let response = sender.send_request(request).await?;
+
+        match response.status() {
+            StatusCode::OK => {
+                if self.written != 0 {
+                    tracing::debug!(%url, "server does not support resuming downloads");
+                    self.restart().await?;
+                }
+            }
+            StatusCode::PARTIAL_CONTENT => {
+                let start = response
+                    .headers()
+                    .get(header::CONTENT_RANGE)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(range_start);
+                if start != Some(self.written) {
+                    self.restart().await?;
+                    return Err(FetchError::UnexpectedRange);
+                }
+                tracing::debug!(%url, from = self.written, "resuming download");
+            }
+            // Everything was already downloaded, which the hash will confirm.
+            StatusCode::RANGE_NOT_SATISFIABLE if self.written != 0 => return Ok(None),
+            status if status.is_redirection() => {
+                let location = response
+                    .headers()
+                    .get(header::LOCATION)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| url.join(value).ok())
+                    .ok_or(FetchError::Status(status))?;
+                return Ok(Some(location));
+            }
+            status => return Err(FetchError::Status(status)),
+        }
+
+        let mut body = response.into_body();
+        while let Some(frame) = body.frame().await {
+            if let Ok(data) = frame?.into_data() {
+                self.write(&data).await?;
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Starts an HTTP/1.1 connection, which is driven in the background.
+async fn handshake<S>(stream: S) -> Result<SendRequest<Empty<Bytes>>, FetchError>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream)).await?;
+    tokio::spawn(async move {
+        if let Err(error) = connection.await {
+            tracing::trace!(?error, "download connection failed");
+        }
+    });
+    Ok(sender)
+}
+
+/// Parses the first byte of a `Content-Range` value, such as `bytes 100-199/200`.
+fn range_start(value: &str) -> Option<u64> {
+    let (start, _) = value.strip_prefix("bytes ")?.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use nck_hashing::{SupportedHash, SupportedHasher};
+    use nck_io::fs::TempDir;
+    use rstest::rstest;
+    use tokio::{
+        io::{AsyncReadExt as _, AsyncWriteExt as _},
+        net::TcpListener,
+    };
+    use tokio_rustls::{
+        rustls::{
+            pki_types::{CertificateDer, PrivateKeyDer},
+            ClientConfig, RootCertStore, ServerConfig,
+        },
+        TlsAcceptor,
+    };
+    use url::Url;
+
+    use super::{download, range_start, tls_config, FetchError};
+    use crate::store::Store;
+
+    const CONTENTS: &[u8] = b"the quick brown fox jumps over the lazy dog";
+
+    fn hash(contents: &[u8]) -> SupportedHash {
+        let mut hasher = SupportedHasher::blake3();
+        hasher.update(contents);
+        hasher.finalize()
+    }
+
+    async fn download_to_vec(
+        url: &Url,
+        expected: &SupportedHash,
+        tls: Arc<ClientConfig>,
+    ) -> Result<Vec<u8>, FetchError> {
+        let dir = TempDir::new().unwrap();
+        let path = dir.as_path().join("download");
+        let mut file = tokio::fs::File::options()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await
+            .unwrap();
+        download(url, expected, &mut file, tls).await?;
+        Ok(std::fs::read(&path).unwrap())
+    }
+
+    #[rstest]
+    #[case("bytes 100-199/200", Some(100))]
+    #[case("bytes 0-0/*", Some(0))]
+    #[case("bytes */200", None)]
+    #[case("items 1-2/3", None)]
+    fn content_range(#[case] value: &str, #[case] expected: Option<u64>) {
+        assert_eq!(expected, range_start(value));
+    }
+
+    #[tokio::test]
+    async fn fetch_file_url() {
+        let dir = TempDir::new().unwrap();
+        let source = dir.as_path().join("source");
+        std::fs::write(&source, CONTENTS).unwrap();
+        let url = Url::from_file_path(&source).unwrap();
+
+        let downloaded = download_to_vec(&url, &hash(CONTENTS), tls_config())
+            .await
+            .unwrap();
+        assert_eq!(CONTENTS, downloaded);
+
+        let wrong = hash(b"something else");
+        let error = download_to_vec(&url, &wrong, tls_config())
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(error, FetchError::Mismatch { expected, actual } if expected == wrong && actual == hash(CONTENTS))
+        );
+    }
+
+    /// Serves `CONTENTS`, dropping the first connection half way through the body.
+    async fn flaky_server() -> (Url, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/file", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let log = requests.clone();
+        tokio::spawn(async move {
+            let mut attempt = 0;
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0];
+                    stream.read_exact(&mut byte).await.unwrap();
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8(request).unwrap().to_ascii_lowercase();
+                let start = request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("range: bytes="))
+                    .and_then(|range| range.trim_end_matches('-').parse().ok());
+                log.lock().unwrap().push(request);
+
+                let response = match start {
+                    Some(start) => format!(
+                        "HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {start}-{}/{}\r\n\r\n",
+                        CONTENTS.len() - start,
+                        CONTENTS.len() - 1,
+                        CONTENTS.len()
+                    ),
+                    None => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", CONTENTS.len()),
+                };
+                stream.write_all(response.as_bytes()).await.unwrap();
+                let body = &CONTENTS[start.unwrap_or_default()..];
+                if attempt == 0 {
+                    stream.write_all(&body[..body.len() / 2]).await.unwrap();
+                } else {
+                    stream.write_all(body).await.unwrap();
+                }
+                stream.shutdown().await.unwrap();
+                attempt += 1;
+            }
+        });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn fetch_http_resumes() {
+        let (url, requests) = flaky_server().await;
+
+        let downloaded = download_to_vec(&url, &hash(CONTENTS), tls_config())
+            .await
+            .unwrap();
+        assert_eq!(CONTENTS, downloaded);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(2, requests.len());
+        assert!(!requests[0].contains("range:"));
+        let resumed_from = CONTENTS.len() / 2;
+        assert!(requests[1].contains(&format!("range: bytes={resumed_from}-")));
+    }
+
+    #[tokio::test]
+    async fn fetch_http_not_found() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url: Url = format!("http://{}/missing", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = [0; 1024];
+            let _ = stream.read(&mut buffer).await.unwrap();
+            stream
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            // Retrying would hang, because nothing accepts another connection.
+            std::future::pending::<()>().await;
+        });
+
+        let error = download_to_vec(&url, &hash(CONTENTS), tls_config())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, FetchError::Status(status) if status.as_u16() == 404));
+    }
+
+    /// Serves `CONTENTS` over TLS, with a certificate for `localhost` that is returned so that it can be trusted.
+    async fn tls_server() -> (Url, CertificateDer<'static>) {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let cert = generated.cert.der().clone();
+        let key = PrivateKeyDer::Pkcs8(generated.key_pair.serialize_der().into());
+        let config = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(vec![cert.clone()], key)
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!(
+            "https://localhost:{}/file",
+            listener.local_addr().unwrap().port()
+        )
+        .parse()
+        .unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let Ok(mut stream) = acceptor.accept(stream).await else {
+                    continue;
+                };
+                let mut buffer = [0; 1024];
+                let _ = stream.read(&mut buffer).await.unwrap();
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+                    CONTENTS.len()
+                );
+                stream.write_all(head.as_bytes()).await.unwrap();
+                stream.write_all(CONTENTS).await.unwrap();
+                stream.shutdown().await.unwrap();
+            }
+        });
+        (url, cert)
+    }
+
+    #[tokio::test]
+    async fn fetch_https() {
+        let (url, cert) = tls_server().await;
+
+        // The certificate isn't signed by a public authority.
+        let error = download_to_vec(&url, &hash(CONTENTS), tls_config())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, FetchError::Tls(_)));
+
+        let mut roots = RootCertStore::empty();
+        roots.add(cert).unwrap();
+        let tls = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let downloaded = download_to_vec(&url, &hash(CONTENTS), Arc::new(tls))
+            .await
+            .unwrap();
+        assert_eq!(CONTENTS, downloaded);
+    }
+
+    #[tokio::test]
+    async fn file_urls_disabled() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::for_test(dir.as_path()).await;
+        let source = dir.as_path().join("source");
+        std::fs::write(&source, CONTENTS).unwrap();
+        let url = Url::from_file_path(&source).unwrap();
+
+        let error = store.fetch(&url, &hash(CONTENTS)).await.unwrap_err();
+        assert!(matches!(error, FetchError::FileUrlsDisabled));
+    }
+}
@@ -0,0 +1,260 @@
+use std::{
+    collections::BTreeSet,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::atomic::AtomicUsize,
+    time::Duration,
+};
+
+use dashmap::{mapref::entry::Entry, DashMap};
+use nck_archive::{EntryTarget, ReadEvent, Reader};
+use nck_hashing::SupportedHash;
+use serde::Serialize;
+
+use super::{BuildStatus, Store};
+
+/// The outcome of a garbage collection pass.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GcReport {
+    /// Whether anything was actually removed.
+    pub dry_run: bool,
+    /// Files that were removed from `files/`.
+    pub files: Vec<SupportedHash>,
+    /// Entries that were removed from `temp/`.
+    pub temp: Vec<String>,
+    pub reclaimed_bytes: u64,
+}
+
+impl Store {
+    /// Removes files and temporary entries that are not reachable from a root.
+    ///
+    /// Roots are locked paths, pinned files, and the manifests of build outputs along with every file they refer to.
+    pub async fn collect_garbage(&self, dry_run: bool) -> anyhow::Result<GcReport> {
+        // Captured outputs are not roots until their build has finished.
+        let _collecting = self.collecting.write().await;
+
+        let mut manifests = Vec::new();
+        for build in self.builds.iter() {
+            if let BuildStatus::Succeeded { outputs } = &build.status {
+                manifests.extend(outputs.values().map(|output| output.manifest));
+            }
+        }
+
+        let state = self.0.clone();
+        let report = tokio::task::spawn_blocking(move || {
+            let mut live: BTreeSet<_> = state.pinned.iter().copied().collect();
+            for manifest in manifests {
+                live.insert(manifest);
+                let path = state.paths.files.join(manifest.to_string());
+                if let Err(error) = read_manifest(&path, &mut live) {
+                    tracing::warn!(%manifest, ?error, "failed to read an output manifest");
+                }
+            }
+            sweep(
+                &state.paths.files,
+                &state.paths.temp,
+                &state.locks,
+                &live,
+                dry_run,
+            )
+        })
+        .await??;
+        if !dry_run {
+            self.forget_files(&report.files).await;
+        }
+
+        tracing::info!(
+            dry_run,
+            files = report.files.len(),
+            temp = report.temp.len(),
+            reclaimed_bytes = report.reclaimed_bytes,
+            "garbage collected"
+        );
+        Ok(report)
+    }
+
+    /// Collects garbage forever, waiting for the interval between each pass.
+    pub async fn collect_periodically(self, interval: Duration) {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(error) = self.collect_garbage(false).await {
+                tracing::error!(?error, "garbage collection failed");
+            }
+        }
+    }
+}
+
+/// Adds the files that a manifest refers to.
+fn read_manifest(path: &Path, live: &mut BTreeSet<SupportedHash>) -> std::io::Result<()> {
+    let mut reader = Reader::new(std::io::BufReader::new(std::fs::File::open(path)?));
+    loop {
+        match reader.next_event()? {
+            ReadEvent::None => return Ok(()),
+            ReadEvent::Entry(entry) => {
+                if let EntryTarget::Data(hash, _) = entry.target() {
+                    live.insert(*hash);
+                }
+            }
+            ReadEvent::Data(_) => return Err(ErrorKind::InvalidData.into()),
+        }
+    }
+}
+
+/// Removes everything that is neither live nor locked.
+///
+/// The lock entry for each path is held while it is removed, so that it can't be locked and opened in the meantime.
+fn sweep(
+    files: &Path,
+    temp: &Path,
+    locks: &DashMap<PathBuf, AtomicUsize>,
+    live: &BTreeSet<SupportedHash>,
+    dry_run: bool,
+) -> std::io::Result<GcReport> {
+    let mut report = GcReport {
+        dry_run,
+        ..Default::default()
+    };
+
+    for entry in std::fs::read_dir(files)? {
+        let path = entry?.path();
+        let Some(hash) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse::<SupportedHash>().ok())
+        else {
+            tracing::warn!(?path, "unexpected file in the store");
+            continue;
+        };
+        if live.contains(&hash) {
+            continue;
+        }
+        if let Some(len) = remove_unlocked(locks, path, dry_run)? {
+            report.files.push(hash);
+            report.reclaimed_bytes += len;
+        }
+    }
+
+    for entry in std::fs::read_dir(temp)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(len) = remove_unlocked(locks, entry.path(), dry_run)? {
+            report.temp.push(name);
+            report.reclaimed_bytes += len;
+        }
+    }
+
+    report.files.sort_unstable();
+    report.temp.sort_unstable();
+    Ok(report)
+}
+
+/// Removes a path unless it is locked, returning the number of bytes it used.
+fn remove_unlocked(
+    locks: &DashMap<PathBuf, AtomicUsize>,
+    path: PathBuf,
+    dry_run: bool,
+) -> std::io::Result<Option<u64>> {
+    let Entry::Vacant(vacant) = locks.entry(path) else {
+        return Ok(None);
+    };
+    let path = vacant.key();
+
+    let len = match disk_usage(path) {
+        Ok(len) => len,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if !dry_run {
+        tracing::debug!(?path, len, "removing garbage");
+        let result = if std::fs::symlink_metadata(path)?.is_dir() {
+            std::fs::remove_dir_all(path)
+        } else {
+            std::fs::remove_file(path)
+        };
+        match result {
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            other => other?,
+        }
+    }
+    Ok(Some(len))
+}
+
+/// Removes everything within a directory, returning the number of entries and bytes that were removed.
+pub(super) fn clear(dir: &Path) -> std::io::Result<(usize, u64)> {
+    let mut removed = (0, 0);
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let len = disk_usage(&path)?;
+        if std::fs::symlink_metadata(&path)?.is_dir() {
+            std::fs::remove_dir_all(&path)?;
+        } else {
+            std::fs::remove_file(&path)?;
+        }
+        removed.0 += 1;
+        removed.1 += len;
+    }
+    Ok(removed)
+}
+
+fn disk_usage(path: &Path) -> std::io::Result<u64> {
+    let stat = std::fs::symlink_metadata(path)?;
+    if !stat.is_dir() {
+        return Ok(stat.len());
+    }
+    let mut len = 0;
+    for entry in std::fs::read_dir(path)? {
+        len += disk_usage(&entry?.path())?;
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeSet, path::PathBuf, sync::atomic::AtomicUsize};
+
+    use dashmap::DashMap;
+    use nck_hashing::SupportedHasher;
+    use nck_io::fs::TempDir;
+
+    use super::sweep;
+
+    #[test]
+    fn sweep_keeps_live_and_locked() {
+        let dir = TempDir::new().unwrap();
+        let files = dir.as_path().join("files");
+        let temp = dir.as_path().join("temp");
+        std::fs::create_dir_all(&files).unwrap();
+        std::fs::create_dir_all(temp.join("pending")).unwrap();
+        std::fs::write(temp.join("pending/file"), "pending").unwrap();
+        std::fs::write(temp.join("upload"), "upload").unwrap();
+
+        let hash = |contents: &str| {
+            let mut hasher = SupportedHasher::blake3();
+            hasher.update(contents.as_bytes());
+            let hash = hasher.finalize();
+            std::fs::write(files.join(hash.to_string()), contents).unwrap();
+            hash
+        };
+        let live = hash("live");
+        let locked = hash("locked");
+        let dead = hash("dead");
+
+        let locks = DashMap::<PathBuf, AtomicUsize>::new();
+        locks.insert(files.join(locked.to_string()), AtomicUsize::new(1));
+        locks.insert(temp.join("upload"), AtomicUsize::new(1));
+        let live = BTreeSet::from([live]);
+
+        let report = sweep(&files, &temp, &locks, &live, true).unwrap();
+        assert_eq!(vec![dead], report.files);
+        assert_eq!(vec!["pending".to_string()], report.temp);
+        assert_eq!(11, report.reclaimed_bytes);
+        assert!(files.join(dead.to_string()).exists());
+
+        let report = sweep(&files, &temp, &locks, &live, false).unwrap();
+        assert_eq!(vec![dead], report.files);
+        assert!(!files.join(dead.to_string()).exists());
+        assert!(!temp.join("pending").exists());
+        assert!(temp.join("upload").exists());
+        assert_eq!(2, std::fs::read_dir(&files).unwrap().count());
+    }
+}
@@ -0,0 +1,337 @@
+use std::{
+    collections::BTreeSet,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use dashmap::mapref::entry::Entry;
+use nck_hashing::{SupportedHash, SupportedHasher};
+use nck_io::fs::TempFile;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{io::AsyncWriteExt as _, sync::watch};
+
+use crate::spec::{OutputReference, PackageName, Spec};
+
+use super::{remove_output, Build, BuildLog, BuildStatus, LogProgress, Store, StoreState};
+
+const RECORD_NAME: &str = "record.json";
+
+/// How far `last_used` has to move before a file record is written again, so that reading a file doesn't rewrite its
+/// record every time.
+const LAST_USED_RESOLUTION: u64 = 60 * 60;
+
+/// What is known about a file under `files/`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileRecord {
+    pub size: u64,
+    /// When the file was added to the store, in seconds since the Unix epoch.
+    pub added: u64,
+    /// When the file was last downloaded or used by a build, in seconds since the Unix epoch.
+    ///
+    /// This is only updated once an hour.
+    pub last_used: u64,
+}
+
+/// What is known about a build, apart from its status.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub name: PackageName,
+    /// The files that the build depends on.
+    pub files: BTreeSet<SupportedHash>,
+    /// The outputs of other builds that the build depends on.
+    pub dependencies: BTreeSet<OutputReference>,
+    /// When the build started, in seconds since the Unix epoch.
+    pub started: u64,
+    /// When the build finished, in seconds since the Unix epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub finished: Option<u64>,
+}
+
+/// A build, as persisted in `builds/<hash>/record.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildRecord {
+    #[serde(flatten)]
+    pub info: BuildInfo,
+    #[serde(flatten)]
+    pub status: BuildStatus,
+}
+
+impl Store {
+    /// Restores the records that were persisted by a previous daemon.
+    pub(super) async fn load_metadata(&self) -> std::io::Result<()> {
+        for (hash, record) in read_records::<FileRecord>(&self.paths.file_records).await? {
+            self.files.insert(hash, record);
+        }
+
+        let mut interrupted = 0;
+        for (hash, mut record) in read_records::<BuildRecord>(&self.paths.builds).await? {
+            let root = self.paths.builds.join(hash.to_string());
+            let unfinished = matches!(record.status, BuildStatus::Queued | BuildStatus::Running);
+            if unfinished {
+                tracing::warn!(%hash, "build was interrupted");
+                self.remove_interrupted_outputs(&root).await;
+                record.status = BuildStatus::failed("interrupted by a daemon restart");
+                record.info.finished = Some(now());
+                interrupted += 1;
+            }
+
+            let log = root.join("log");
+            let len = match tokio::fs::metadata(&log).await {
+                Ok(stat) => stat.len(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+                Err(e) => return Err(e),
+            };
+            let (_, progress) = watch::channel(LogProgress {
+                len,
+                finished: true,
+            });
+            self.builds.insert(
+                hash,
+                Build {
+                    status: record.status,
+                    generation: self.next_generation(),
+                    locks: Vec::new(),
+                    supervisor: None,
+                    log: BuildLog {
+                        path: log,
+                        progress,
+                    },
+                    info: record.info,
+                },
+            );
+            if unfinished {
+                self.save_build(&hash).await;
+            }
+        }
+
+        tracing::info!(
+            files = self.files.len(),
+            builds = self.builds.len(),
+            interrupted,
+            "loaded store metadata"
+        );
+        Ok(())
+    }
+
+    /// Removes whatever an interrupted build left in its outputs.
+    async fn remove_interrupted_outputs(&self, root: &Path) {
+        let spec = match tokio::fs::read_to_string(root.join("spec.toml")).await {
+            Ok(spec) => spec,
+            Err(error) => {
+                tracing::warn!(
+                    ?root,
+                    ?error,
+                    "failed to read the spec of an interrupted build"
+                );
+                return;
+            }
+        };
+        let spec: Spec = match toml::from_str(&spec) {
+            Ok(spec) => spec,
+            Err(error) => {
+                tracing::warn!(
+                    ?root,
+                    ?error,
+                    "failed to parse the spec of an interrupted build"
+                );
+                return;
+            }
+        };
+
+        let paths = spec.paths(&self.paths.store, SupportedHasher::blake3());
+        for name in spec.outputs() {
+            let output = paths.output(name);
+            if let Err(error) = remove_output(&output).await {
+                tracing::warn!(?output, ?error, "failed to remove an interrupted output");
+            }
+        }
+    }
+
+    /// The record of a build, including its status.
+    pub fn build_record(&self, hash: &SupportedHash) -> Option<BuildRecord> {
+        self.builds.get(hash).map(|build| BuildRecord {
+            info: build.info.clone(),
+            status: build.status.clone(),
+        })
+    }
+
+    pub fn file_record(&self, hash: &SupportedHash) -> Option<FileRecord> {
+        self.files.get(hash).map(|record| *record)
+    }
+
+    /// Persists the current state of a build.
+    pub(super) async fn save_build(&self, hash: &SupportedHash) {
+        let Some(record) = self.build_record(hash) else {
+            return;
+        };
+        let path = build_record_path(&self.paths.builds.join(hash.to_string()));
+        if let Err(error) = write_record(&path, &record).await {
+            tracing::warn!(%hash, ?error, "failed to save the build record");
+        }
+    }
+
+    /// Forgets files that have been removed from the store.
+    pub(super) async fn forget_files(&self, hashes: &[SupportedHash]) {
+        for hash in hashes {
+            self.files.remove(hash);
+            let path = self.paths.file_records.join(format!("{hash}.json"));
+            match tokio::fs::remove_file(&path).await {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => tracing::warn!(%hash, ?error, "failed to remove the file record"),
+                Ok(()) => {}
+            }
+        }
+    }
+}
+
+impl StoreState {
+    /// Records that a file is in use, adding it if it is new to the store.
+    pub(super) async fn touch_file(&self, hash: &SupportedHash, size: u64) {
+        let now = now();
+        let record = match self.files.entry(*hash) {
+            Entry::Occupied(mut occupied) => {
+                let record = occupied.get_mut();
+                if record.size == size
+                    && now.saturating_sub(record.last_used) < LAST_USED_RESOLUTION
+                {
+                    return;
+                }
+                record.size = size;
+                record.last_used = now;
+                *record
+            }
+            Entry::Vacant(vacant) => *vacant.insert(FileRecord {
+                size,
+                added: now,
+                last_used: now,
+            }),
+        };
+
+        let path = self.paths.file_records.join(format!("{hash}.json"));
+        if let Err(error) = write_record(&path, &record).await {
+            tracing::warn!(%hash, ?error, "failed to save the file record");
+        }
+    }
+}
+
+/// The current time, in seconds since the Unix epoch.
+pub(super) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// The location of a build record within the directory for its spec.
+fn build_record_path(root: &Path) -> std::path::PathBuf {
+    root.join(RECORD_NAME)
+}
+
+/// Atomically replaces a record.
+async fn write_record(path: &Path, record: &impl Serialize) -> anyhow::Result<()> {
+    let parent = path
+        .parent()
+        .expect("records are always within a directory");
+    let (temp, mut file) = TempFile::new_in(parent).await?;
+    file.write_all(&serde_json::to_vec_pretty(record)?).await?;
+    file.flush().await?;
+    temp.write_to(path).await?;
+    Ok(())
+}
+
+/// Reads every record in a directory, named after the hash it belongs to, skipping any that can't be read.
+///
+/// Build records are within a directory named after the hash, instead.
+async fn read_records<T: DeserializeOwned>(dir: &Path) -> std::io::Result<Vec<(SupportedHash, T)>> {
+    let mut result = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let Some(hash) = path
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse().ok())
+        else {
+            continue;
+        };
+        let record = if entry.file_type().await?.is_dir() {
+            build_record_path(&path)
+        } else {
+            path
+        };
+        match read_record(&record).await {
+            Ok(Some(value)) => result.push((hash, value)),
+            Ok(None) => {}
+            Err(error) => tracing::warn!(?record, ?error, "ignoring unreadable record"),
+        }
+    }
+    Ok(result)
+}
+
+async fn read_record<T: DeserializeOwned>(path: &Path) -> anyhow::Result<Option<T>> {
+    match tokio::fs::read(path).await {
+        Ok(data) => Ok(Some(serde_json::from_slice(&data)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use nck_hashing::SupportedHasher;
+    use nck_io::fs::TempDir;
+
+    use crate::store::{BuildStatus, Store};
+
+    use super::{BuildInfo, BuildRecord, LAST_USED_RESOLUTION};
+
+    #[test]
+    fn build_record_round_trip() {
+        let record = BuildRecord {
+            info: BuildInfo {
+                name: "bootstrap-0.0.1".parse().unwrap(),
+                files: BTreeSet::new(),
+                dependencies: BTreeSet::new(),
+                started: 1,
+                finished: Some(2),
+            },
+            status: BuildStatus::Cancelled,
+        };
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(
+            r#"{"name":"bootstrap-0.0.1","files":[],"dependencies":[],"started":1,"finished":2,"status":"cancelled"}"#,
+            json
+        );
+        assert_eq!(record, serde_json::from_str(&json).unwrap());
+    }
+
+    #[tokio::test]
+    async fn touch_file_writes_changes() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::for_test(dir.as_path()).await;
+        let hash = SupportedHasher::blake3().finalize();
+        let path = dir.as_path().join(format!("metadata/files/{hash}.json"));
+
+        store.touch_file(&hash, 1).await;
+        let added = store.file_record(&hash).unwrap();
+        assert!(path.exists());
+
+        // Using the file again straight away doesn't change the record.
+        std::fs::remove_file(&path).unwrap();
+        store.touch_file(&hash, 1).await;
+        assert_eq!(Some(added), store.file_record(&hash));
+        assert!(!path.exists());
+
+        store.files.get_mut(&hash).unwrap().last_used -= LAST_USED_RESOLUTION;
+        store.touch_file(&hash, 1).await;
+        assert!(store.file_record(&hash).unwrap().last_used >= added.last_used);
+        assert!(path.exists());
+
+        std::fs::remove_file(&path).unwrap();
+        store.touch_file(&hash, 2).await;
+        assert_eq!(2, store.file_record(&hash).unwrap().size);
+        assert!(path.exists());
+    }
+}
@@ -0,0 +1,169 @@
+use std::{
+    collections::VecDeque,
+    fs::Permissions,
+    os::unix::fs::PermissionsExt as _,
+    path::{Path, PathBuf},
+};
+
+use anyhow::bail;
+use nck_archive::{Entry, EntryFlags, Writer};
+use nck_hashing::{SupportedHash, SupportedHasher};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWriteExt as _};
+
+use super::{Store, StoreLock};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// An output of a successful build.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildOutput {
+    /// The read-only directory containing the output.
+    pub path: PathBuf,
+    /// The archive, under `files/`, that lists the entries of the output.
+    pub manifest: SupportedHash,
+}
+
+/// Captures a build output directory into the store.
+///
+/// The contents of each file are written to `files/`, and the structure of the output is written as an archive that
+/// contains only entries, which is referred to as the manifest. The output directory is then made read-only.
+pub(super) async fn capture(store: &Store, root: &Path) -> anyhow::Result<BuildOutput> {
+    let mut writer = Writer::new(Vec::new())?;
+    let mut directories = Vec::new();
+
+    let mut pending = VecDeque::from([PathBuf::new()]);
+    while let Some(directory) = pending.pop_front() {
+        let mut nested = Vec::new();
+        let mut entries = tokio::fs::read_dir(root.join(&directory)).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            nested.push(directory.join(entry.file_name()));
+        }
+        nested.sort_unstable();
+
+        for path in nested {
+            let full = root.join(&path);
+            let stat = tokio::fs::symlink_metadata(&full).await?;
+
+            if stat.is_dir() {
+                writer.write_entry(Entry::directory(&path))?;
+                directories.push(full);
+                pending.push_back(path);
+            } else if stat.is_file() {
+                let executable = (stat.permissions().mode() & 0o111) != 0;
+                let flags = if executable {
+                    EntryFlags::EXECUTABLE
+                } else {
+                    EntryFlags::empty()
+                };
+                let mut file = tokio::fs::File::open(&full).await?;
+                let (hash, _) = write_blob(store, &mut file).await?;
+                writer.write_entry(Entry::data(&path, hash, Some(flags)))?;
+                let mode = if executable { 0o555 } else { 0o444 };
+                tokio::fs::set_permissions(&full, Permissions::from_mode(mode)).await?;
+            } else if stat.is_symlink() {
+                let target = tokio::fs::read_link(&full).await?;
+                // The mode of a link is meaningless, and always allows everything.
+                writer.write_entry(Entry::link(&path, target, None))?;
+            } else {
+                bail!("{full:?} is not a file, directory or link");
+            }
+        }
+    }
+
+    // Children are made read-only before their parents.
+    for directory in directories.iter().rev().map(PathBuf::as_path).chain([root]) {
+        tokio::fs::set_permissions(directory, Permissions::from_mode(0o555)).await?;
+    }
+
+    let manifest = writer.into_inner();
+    let (manifest, _) = write_blob(store, &mut manifest.as_slice()).await?;
+    Ok(BuildOutput {
+        path: root.to_path_buf(),
+        manifest,
+    })
+}
+
+/// Writes data to `files/`, unless it is already present.
+pub(super) async fn write_blob(
+    store: &Store,
+    reader: &mut (impl AsyncRead + Unpin),
+) -> std::io::Result<(SupportedHash, StoreLock)> {
+    let mut file = store.create_file().await?;
+    let mut hasher = SupportedHasher::blake3();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        file.write_all(&buf[..n]).await?;
+    }
+    file.flush().await?;
+
+    let hash = hasher.finalize();
+    let lock = file.complete(&hash).await?;
+    Ok((hash, lock))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs::Permissions, os::unix::fs::PermissionsExt as _};
+
+    use nck_archive::{Entry, EntryFlags, ReadEvent, Reader};
+    use nck_io::fs::TempDir;
+
+    use super::capture;
+    use crate::store::Store;
+
+    #[tokio::test]
+    async fn capture_output() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::for_test(&dir.as_path().join("store")).await;
+        let output = dir.as_path().join("out");
+        std::fs::create_dir_all(output.join("bin")).unwrap();
+        std::fs::write(output.join("bin/tool"), "#!/bin/sh").unwrap();
+        std::fs::set_permissions(output.join("bin/tool"), Permissions::from_mode(0o755)).unwrap();
+        std::fs::write(output.join("data"), "data").unwrap();
+        std::os::unix::fs::symlink("bin/tool", output.join("tool")).unwrap();
+
+        let captured = capture(&store, &output).await.unwrap();
+
+        let manifest = store.get_file(&captured.manifest).await.unwrap();
+        let mut reader = Reader::new(tokio::io::BufReader::new(manifest));
+        let mut entries = Vec::new();
+        while let ReadEvent::Entry(entry) = reader.next_event_async().await.unwrap() {
+            entries.push(entry);
+        }
+        let hash = |path: &str| match entries.iter().find(|e| e.path().to_str() == Some(path)) {
+            Some(entry) => match entry.target() {
+                nck_archive::EntryTarget::Data(hash, _) => *hash,
+                other => panic!("{path} is {other:?}"),
+            },
+            None => panic!("{path} was not captured"),
+        };
+        let (tool, data) = (hash("bin/tool"), hash("data"));
+        assert_eq!(
+            vec![
+                Entry::directory("bin"),
+                Entry::data("data", data, None),
+                // Links are always executable on disk, but are never flagged as such.
+                Entry::link("tool", "bin/tool", None),
+                Entry::data("bin/tool", tool, Some(EntryFlags::EXECUTABLE)),
+            ],
+            entries
+        );
+
+        let mode = |path: &str| {
+            std::fs::metadata(output.join(path))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        assert_eq!(0o555, mode("bin/tool"));
+        assert_eq!(0o444, mode("data"));
+        assert_eq!(0o555, mode("bin"));
+    }
+}
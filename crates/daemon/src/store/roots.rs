@@ -0,0 +1,215 @@
+use std::{collections::BTreeMap, io::ErrorKind, path::Path};
+
+use nck_hashing::SupportedHash;
+
+use crate::spec::RootName;
+
+use super::{DecrementLock, Store};
+
+/// A named garbage collection root, which holds a lock on a store file until it is removed.
+///
+/// Roots are persisted as links from `roots/<name>` to the file.
+#[derive(Debug)]
+pub(super) struct Root {
+    hash: SupportedHash,
+}
+
+impl Store {
+    /// Restores the roots that were persisted by a previous daemon.
+    pub(super) async fn load_roots(&self) -> std::io::Result<()> {
+        let mut entries = tokio::fs::read_dir(&self.paths.roots).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let name = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse().ok());
+            let target = tokio::fs::read_link(&path).await.ok();
+            let hash = target
+                .as_deref()
+                .and_then(Path::file_name)
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.parse().ok());
+
+            match (name, hash) {
+                (Some(name), Some(hash)) => {
+                    tracing::debug!(%name, %hash, "loaded root");
+                    self.insert_root(name, hash);
+                }
+                _ => tracing::warn!(?path, ?target, "ignoring invalid root"),
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_root(&self, name: RootName, hash: SupportedHash) -> Option<SupportedHash> {
+        self.increase_lock(self.paths.files.join(hash.to_string()));
+        let previous = self.roots.insert(name, Root { hash })?.hash;
+        self.decrease_lock(self.paths.files.join(previous.to_string()));
+        Some(previous)
+    }
+
+    /// All roots, and the files they keep.
+    pub fn roots(&self) -> BTreeMap<RootName, SupportedHash> {
+        self.roots
+            .iter()
+            .map(|root| (root.key().clone(), root.hash))
+            .collect()
+    }
+
+    pub fn root(&self, name: &RootName) -> Option<SupportedHash> {
+        self.roots.get(name).map(|root| root.hash)
+    }
+
+    /// Creates or replaces a root, returning the file that it previously kept.
+    ///
+    /// The file must already be in the store.
+    pub async fn set_root(
+        &self,
+        name: RootName,
+        hash: SupportedHash,
+    ) -> std::io::Result<Option<SupportedHash>> {
+        let file = self.paths.files.join(hash.to_string());
+        let lock = DecrementLock::new(file.clone(), self.0.clone());
+        if !tokio::fs::try_exists(&file).await? {
+            return Err(ErrorKind::NotFound.into());
+        }
+
+        // Root names can't start with '.', so this can't replace another root.
+        let temp = self
+            .paths
+            .roots
+            .join(format!(".{name}.{}", uuid::Uuid::new_v4().simple()));
+        tokio::fs::symlink(Path::new("../files").join(hash.to_string()), &temp).await?;
+        if let Err(error) = tokio::fs::rename(&temp, self.paths.roots.join(name.as_ref())).await {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(error);
+        }
+
+        tracing::info!(%name, %hash, "set root");
+        let previous = self.insert_root(name, hash);
+        drop(lock);
+        Ok(previous)
+    }
+
+    /// Removes a root, returning the file that it kept.
+    pub async fn remove_root(&self, name: &RootName) -> std::io::Result<Option<SupportedHash>> {
+        match tokio::fs::remove_file(self.paths.roots.join(name.as_ref())).await {
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            other => other?,
+        }
+
+        let Some((_, Root { hash })) = self.roots.remove(name) else {
+            return Ok(None);
+        };
+        self.decrease_lock(self.paths.files.join(hash.to_string()));
+        tracing::info!(%name, %hash, "removed root");
+        Ok(Some(hash))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, sync::atomic::Ordering};
+
+    use nck_hashing::{SupportedHash, SupportedHasher};
+    use nck_io::fs::TempDir;
+
+    use crate::{spec::RootName, store::Store};
+
+    /// Adds a file to the store, returning its hash.
+    fn add_file(store: &Store, contents: &str) -> SupportedHash {
+        let mut hasher = SupportedHasher::blake3();
+        hasher.update(contents.as_bytes());
+        let hash = hasher.finalize();
+        std::fs::write(store.paths.files.join(hash.to_string()), contents).unwrap();
+        hash
+    }
+
+    fn lock_count(store: &Store, hash: &SupportedHash) -> usize {
+        store
+            .locks
+            .get(&store.paths.files.join(hash.to_string()))
+            .map_or(0, |count| count.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn set_replace_and_remove() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::for_test(dir.as_path()).await;
+        let first = add_file(&store, "first");
+        let second = add_file(&store, "second");
+        let name: RootName = "pkg".parse().unwrap();
+
+        assert_eq!(None, store.set_root(name.clone(), first).await.unwrap());
+        assert_eq!(Some(first), store.root(&name));
+        assert_eq!(1, lock_count(&store, &first));
+
+        assert_eq!(
+            Some(first),
+            store.set_root(name.clone(), second).await.unwrap()
+        );
+        assert_eq!(BTreeMap::from([(name.clone(), second)]), store.roots());
+        assert_eq!(0, lock_count(&store, &first));
+        assert_eq!(1, lock_count(&store, &second));
+        let link = dir.as_path().join("roots/pkg");
+        assert_eq!(
+            std::path::Path::new("../files").join(second.to_string()),
+            std::fs::read_link(&link).unwrap()
+        );
+
+        assert_eq!(Some(second), store.remove_root(&name).await.unwrap());
+        assert_eq!(None, store.root(&name));
+        assert_eq!(0, lock_count(&store, &second));
+        assert!(!link.exists());
+        assert_eq!(None, store.remove_root(&name).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn shared_file() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::for_test(dir.as_path()).await;
+        let hash = add_file(&store, "shared");
+        let a: RootName = "a".parse().unwrap();
+        let b: RootName = "b".parse().unwrap();
+
+        store.set_root(a.clone(), hash).await.unwrap();
+        store.set_root(b.clone(), hash).await.unwrap();
+        assert_eq!(2, lock_count(&store, &hash));
+        store.remove_root(&a).await.unwrap();
+        assert_eq!(1, lock_count(&store, &hash));
+        store.remove_root(&b).await.unwrap();
+        assert_eq!(0, lock_count(&store, &hash));
+    }
+
+    #[tokio::test]
+    async fn missing_file() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::for_test(dir.as_path()).await;
+        let hash = SupportedHasher::blake3().finalize();
+
+        let error = store
+            .set_root("pkg".parse().unwrap(), hash)
+            .await
+            .unwrap_err();
+        assert_eq!(std::io::ErrorKind::NotFound, error.kind());
+        assert!(store.roots().is_empty());
+        assert_eq!(0, lock_count(&store, &hash));
+    }
+
+    #[tokio::test]
+    async fn load_after_restart() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::for_test(dir.as_path()).await;
+        let hash = add_file(&store, "kept");
+        let name: RootName = "pkg".parse().unwrap();
+        store.set_root(name.clone(), hash).await.unwrap();
+        // Invalid entries are skipped rather than failing the restart.
+        std::fs::write(dir.as_path().join("roots/not-a-link"), "").unwrap();
+        drop(store);
+
+        let store = Store::for_test(dir.as_path()).await;
+        assert_eq!(BTreeMap::from([(name, hash)]), store.roots());
+        assert_eq!(1, lock_count(&store, &hash));
+    }
+}
@@ -0,0 +1,212 @@
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use nck_archive::{EntryTarget, ReadEvent, Reader, Writer};
+use nck_hashing::{SupportedHash, SupportedHasher};
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWriteExt as _};
+
+use super::{output::write_blob, Store, StoreLock};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Error)]
+pub enum UnpackError {
+    #[error("invalid archive")]
+    Invalid(#[source] std::io::Error),
+    #[error("data in the archive was declared as {declared}, but is {actual}")]
+    Mismatch {
+        declared: SupportedHash,
+        actual: SupportedHash,
+    },
+    #[error("{path:?} refers to {hash}, which is neither in the archive nor the store")]
+    Missing { path: PathBuf, hash: SupportedHash },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl UnpackError {
+    fn from_read(error: std::io::Error) -> Self {
+        match error.kind() {
+            ErrorKind::InvalidData | ErrorKind::UnexpectedEof => Self::Invalid(error),
+            _ => Self::Io(error),
+        }
+    }
+}
+
+/// The files that an archive was unpacked into.
+#[derive(Debug)]
+pub struct UnpackedArchive {
+    /// The archive, under `files/`, that lists the entries of the unpacked archive.
+    pub manifest: SupportedHash,
+    /// Every file that the manifest refers to, including the manifest itself.
+    pub locks: BTreeMap<SupportedHash, StoreLock>,
+}
+
+impl Store {
+    /// Writes each data blob in an archive to `files/`, along with a manifest of its entries.
+    ///
+    /// Entries may refer to data that is already in the store instead of including it, so that only the files
+    /// that the store is missing need to be sent.
+    pub async fn unpack(
+        &self,
+        reader: impl AsyncRead + Unpin + Send,
+    ) -> Result<UnpackedArchive, UnpackError> {
+        let mut reader = Reader::new(reader);
+        let mut manifest = Writer::new(Vec::new())?;
+        let mut locks = BTreeMap::new();
+        let mut referenced = Vec::new();
+
+        loop {
+            match reader
+                .next_event_async()
+                .await
+                .map_err(UnpackError::from_read)?
+            {
+                ReadEvent::None => break,
+                ReadEvent::Data(mut data) => {
+                    let mut file = self.create_file().await?;
+                    let mut hasher = SupportedHasher::blake3();
+                    let mut buf = vec![0u8; CHUNK_SIZE];
+                    loop {
+                        let n = data.read(&mut buf).await.map_err(UnpackError::from_read)?;
+                        if n == 0 {
+                            break;
+                        }
+                        hasher.update(&buf[..n]);
+                        file.write_all(&buf[..n]).await?;
+                    }
+                    file.flush().await?;
+
+                    let actual = hasher.finalize();
+                    let declared = data
+                        .hash()
+                        .ok_or_else(|| UnpackError::Invalid(ErrorKind::UnexpectedEof.into()))?;
+                    if declared != actual {
+                        return Err(UnpackError::Mismatch { declared, actual });
+                    }
+                    let lock = file.complete(&actual).await?;
+                    locks.insert(actual, lock);
+                }
+                ReadEvent::Entry(entry) => {
+                    if let EntryTarget::Data(hash, _) = entry.target() {
+                        referenced.push((entry.path().to_path_buf(), *hash));
+                    }
+                    manifest.write_entry(entry)?;
+                }
+            }
+        }
+
+        // Data can come after the entries that refer to it.
+        for (path, hash) in referenced {
+            if locks.contains_key(&hash) {
+                continue;
+            }
+            let lock = self.get_referenced_file(&path, &hash).await?;
+            locks.insert(hash, lock);
+        }
+
+        let manifest = manifest.into_inner();
+        let (manifest, lock) = write_blob(self, &mut manifest.as_slice()).await?;
+        locks.insert(manifest, lock);
+        tracing::debug!(%manifest, files = locks.len(), "archive unpacked");
+
+        Ok(UnpackedArchive { manifest, locks })
+    }
+
+    async fn get_referenced_file(
+        &self,
+        path: &Path,
+        hash: &SupportedHash,
+    ) -> Result<StoreLock, UnpackError> {
+        match self.get_file(hash).await {
+            Ok(lock) => Ok(lock),
+            Err(e) if e.kind() == ErrorKind::NotFound => Err(UnpackError::Missing {
+                path: path.to_path_buf(),
+                hash: *hash,
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write as _;
+
+    use nck_archive::{Entry, Writer};
+    use nck_hashing::{SupportedHash, SupportedHasher};
+    use nck_io::fs::TempDir;
+
+    use super::UnpackError;
+    use crate::store::Store;
+
+    /// An archive with a blob written in several chunks, and an entry that refers to it.
+    fn archive() -> (Vec<u8>, SupportedHash) {
+        let writer = Writer::new(Vec::new()).unwrap();
+        let mut data = writer.write_data(SupportedHasher::blake3()).unwrap();
+        // Each write is a separate chunk.
+        for chunk in ["hello", " ", "world"] {
+            data.write_all(chunk.as_bytes()).unwrap();
+        }
+        let (mut writer, hash) = data.finish().unwrap();
+        writer
+            .write_entry(Entry::data("greeting", hash, None))
+            .unwrap();
+        (writer.into_inner(), hash)
+    }
+
+    #[tokio::test]
+    async fn unpack_archive() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::for_test(dir.as_path()).await;
+        let (archive, hash) = archive();
+
+        let unpacked = store.unpack(archive.as_slice()).await.unwrap();
+        assert_eq!(
+            "hello world",
+            std::fs::read_to_string(dir.as_path().join(format!("files/{hash}"))).unwrap()
+        );
+        let mut expected = vec![hash, unpacked.manifest];
+        expected.sort();
+        assert_eq!(expected, unpacked.locks.into_keys().collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn unpack_mismatch() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::for_test(dir.as_path()).await;
+        let (mut archive, declared) = archive();
+        // Corrupts the first byte of the blob, after the header, the event type and the chunk length.
+        archive[8] = b'j';
+
+        let error = store.unpack(archive.as_slice()).await.unwrap_err();
+        assert!(matches!(error, UnpackError::Mismatch { declared: d, .. } if d == declared));
+        assert!(!dir.as_path().join(format!("files/{declared}")).exists());
+    }
+
+    #[tokio::test]
+    async fn unpack_missing_blob() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::for_test(dir.as_path()).await;
+        let missing = SupportedHasher::blake3().finalize();
+        let mut writer = Writer::new(Vec::new()).unwrap();
+        writer
+            .write_entry(Entry::data("missing", missing, None))
+            .unwrap();
+        let archive = writer.into_inner();
+
+        let error = store.unpack(archive.as_slice()).await.unwrap_err();
+        assert!(
+            matches!(error, UnpackError::Missing { path, hash } if path.as_os_str() == "missing" && hash == missing)
+        );
+
+        // Files that are already in the store don't need to be in the archive.
+        std::fs::write(dir.as_path().join(format!("files/{missing}")), "").unwrap();
+        let unpacked = store.unpack(archive.as_slice()).await.unwrap();
+        assert!(unpacked.locks.contains_key(&missing));
+    }
+}
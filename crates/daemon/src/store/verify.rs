@@ -0,0 +1,282 @@
+use std::{
+    io::{ErrorKind, Read},
+    path::{Path, PathBuf},
+};
+
+use dashmap::mapref::entry::Entry;
+use nck_hashing::SupportedHash;
+use serde::Serialize;
+
+use super::{Store, StoreState};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A problem found while verifying the store.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum VerifyIssue {
+    /// The contents of a file do not match its name.
+    Mismatch {
+        expected: SupportedHash,
+        actual: SupportedHash,
+    },
+    /// A file is shorter than the size that was recorded when it was added, which is what an interrupted write
+    /// leaves behind.
+    Truncated {
+        expected: SupportedHash,
+        size: u64,
+        recorded: u64,
+    },
+    /// A file could not be read.
+    Unreadable {
+        expected: SupportedHash,
+        error: String,
+    },
+    /// An entry that is not named after a hash, or a temporary entry that nothing is using.
+    Stray { path: PathBuf },
+}
+
+/// The outcome of verifying the store.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VerifyReport {
+    /// Whether the affected entries were moved to `quarantine/`.
+    pub quarantined: bool,
+    /// The number of files that were rehashed.
+    pub checked: usize,
+    pub issues: Vec<VerifyIssue>,
+}
+
+impl Store {
+    /// Rehashes every file in the store and looks for stray entries, optionally moving anything wrong to
+    /// `quarantine/`.
+    pub async fn verify(&self, quarantine: bool) -> anyhow::Result<VerifyReport> {
+        let state = self.0.clone();
+        let report = tokio::task::spawn_blocking(move || verify(&state, quarantine)).await??;
+
+        if report.quarantined {
+            let quarantined: Vec<_> = report
+                .issues
+                .iter()
+                .filter_map(|issue| match issue {
+                    VerifyIssue::Mismatch { expected, .. }
+                    | VerifyIssue::Truncated { expected, .. }
+                    | VerifyIssue::Unreadable { expected, .. } => Some(*expected),
+                    VerifyIssue::Stray { .. } => None,
+                })
+                .collect();
+            self.forget_files(&quarantined).await;
+        }
+
+        if report.issues.is_empty() {
+            tracing::info!(checked = report.checked, "store verified");
+        } else {
+            tracing::warn!(
+                checked = report.checked,
+                issues = report.issues.len(),
+                quarantine,
+                "store verification found issues"
+            );
+        }
+        Ok(report)
+    }
+}
+
+fn verify(state: &StoreState, quarantine: bool) -> std::io::Result<VerifyReport> {
+    let paths = &state.paths;
+    let mut report = VerifyReport {
+        quarantined: quarantine,
+        ..Default::default()
+    };
+
+    let mut entries = std::fs::read_dir(&paths.files)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_unstable_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let issue = match path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.parse::<SupportedHash>().ok())
+        {
+            Some(expected) => {
+                report.checked += 1;
+                let recorded = state.files.get(&expected).map(|record| record.size);
+                match check_file(&path, &expected, recorded) {
+                    Ok(None) => continue,
+                    Ok(Some(issue)) => issue,
+                    Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                    Err(e) => VerifyIssue::Unreadable {
+                        expected,
+                        error: e.to_string(),
+                    },
+                }
+            }
+            // Files are copied into place under a temporary name when the temporary directory is on another
+            // filesystem.
+            None if state.locks.contains_key(&path) => continue,
+            None => VerifyIssue::Stray { path: path.clone() },
+        };
+
+        tracing::warn!(?path, ?issue, "store file failed verification");
+        if quarantine {
+            move_to_quarantine(&paths.store, &path)?;
+        }
+        report.issues.push(issue);
+    }
+
+    let mut entries = std::fs::read_dir(&paths.temp)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_unstable_by_key(|entry| entry.file_name());
+    for entry in entries {
+        // The lock entry is held so that the path can't be locked while it is moved.
+        let Entry::Vacant(vacant) = state.locks.entry(entry.path()) else {
+            continue;
+        };
+        let path = vacant.key();
+        tracing::warn!(?path, "stray temporary entry");
+        if quarantine {
+            match move_to_quarantine(&paths.store, path) {
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                other => other?,
+            }
+        }
+        report
+            .issues
+            .push(VerifyIssue::Stray { path: path.clone() });
+    }
+
+    Ok(report)
+}
+
+/// Rehashes a single file, comparing its length with the recorded size if there is one.
+fn check_file(
+    path: &Path,
+    expected: &SupportedHash,
+    recorded: Option<u64>,
+) -> std::io::Result<Option<VerifyIssue>> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = expected.create_matching_hasher();
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut len = 0u64;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        len += n as u64;
+        hasher.update(&buf[..n]);
+    }
+
+    let actual = hasher.finalize();
+    Ok(match recorded {
+        _ if actual == *expected => None,
+        Some(recorded) if len < recorded => Some(VerifyIssue::Truncated {
+            expected: *expected,
+            size: len,
+            recorded,
+        }),
+        _ => Some(VerifyIssue::Mismatch {
+            expected: *expected,
+            actual,
+        }),
+    })
+}
+
+/// Moves an entry into `quarantine/`, keeping its name unless that is already taken.
+fn move_to_quarantine(store: &Path, path: &Path) -> std::io::Result<()> {
+    let quarantine = store.join("quarantine");
+    std::fs::create_dir_all(&quarantine)?;
+
+    let name = path.file_name().ok_or(ErrorKind::InvalidInput)?;
+    let mut dest = quarantine.join(name);
+    while dest.exists() {
+        let mut unique = name.to_os_string();
+        unique.push(format!(".{}", uuid::Uuid::new_v4().simple()));
+        dest = quarantine.join(unique);
+    }
+
+    tracing::info!(?path, ?dest, "quarantining");
+    std::fs::rename(path, dest)
+}
+
+#[cfg(test)]
+mod test {
+    use nck_hashing::SupportedHasher;
+    use nck_io::fs::TempDir;
+
+    use super::{check_file, VerifyIssue};
+    use crate::store::Store;
+
+    #[test]
+    fn check_file_issues() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.as_path().join("file");
+
+        let mut hasher = SupportedHasher::blake3();
+        hasher.update(b"contents");
+        let expected = hasher.finalize();
+
+        std::fs::write(&path, "contents").unwrap();
+        assert_eq!(None, check_file(&path, &expected, Some(8)).unwrap());
+
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(
+            Some(VerifyIssue::Truncated {
+                expected,
+                size: 0,
+                recorded: 8
+            }),
+            check_file(&path, &expected, Some(8)).unwrap()
+        );
+
+        // Part of the file was written before the daemon stopped.
+        std::fs::write(&path, "cont").unwrap();
+        assert_eq!(
+            Some(VerifyIssue::Truncated {
+                expected,
+                size: 4,
+                recorded: 8
+            }),
+            check_file(&path, &expected, Some(8)).unwrap()
+        );
+        // Without a record, there is no way to tell a short file from a corrupted one.
+        assert!(matches!(
+            check_file(&path, &expected, None).unwrap(),
+            Some(VerifyIssue::Mismatch { .. })
+        ));
+
+        std::fs::write(&path, "corrupted").unwrap();
+        assert!(matches!(
+            check_file(&path, &expected, Some(8)).unwrap(),
+            Some(VerifyIssue::Mismatch { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn quarantine_forgets_files() {
+        let dir = TempDir::new().unwrap();
+        let store = Store::for_test(dir.as_path()).await;
+        let mut hasher = SupportedHasher::blake3();
+        hasher.update(b"contents");
+        let expected = hasher.finalize();
+        let path = dir.as_path().join(format!("files/{expected}"));
+        let record = dir
+            .as_path()
+            .join(format!("metadata/files/{expected}.json"));
+        std::fs::write(&path, "corrupted").unwrap();
+        store.touch_file(&expected, 8).await;
+        assert!(record.exists());
+
+        let report = store.verify(false).await.unwrap();
+        assert_eq!(1, report.issues.len());
+        assert!(store.file_record(&expected).is_some());
+
+        let report = store.verify(true).await.unwrap();
+        assert_eq!(1, report.issues.len());
+        assert!(!path.exists());
+        assert!(dir
+            .as_path()
+            .join(format!("quarantine/{expected}"))
+            .exists());
+        assert_eq!(None, store.file_record(&expected));
+        assert!(!record.exists());
+    }
+}
@@ -1,6 +1,6 @@
 use std::str::FromStr;
 
-use serde::{Deserialize, Serialize};
+use serde::{de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
 use thiserror::Error;
 
 use crate::{base32::Base32, StableHash, StableHasher, StableHasherExt};
@@ -44,11 +44,60 @@ impl StableHasher for SupportedHasher {
     }
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+/// A hash produced by one of the [`SupportedHasher`]s.
+///
+/// Human-readable formats (such as JSON and TOML) serialize the hash as its string representation, e.g.
+/// `blake3-...`. Binary formats use a compact tagged representation.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SupportedHash {
     Blake3([u8; 32]),
 }
 
+/// The binary serialized form of [`SupportedHash`].
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "SupportedHash")]
+enum BinarySupportedHash {
+    Blake3([u8; 32]),
+}
+
+impl Serialize for SupportedHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            match *self {
+                SupportedHash::Blake3(h) => BinarySupportedHash::Blake3(h).serialize(serializer),
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SupportedHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(SupportedHashVisitor)
+        } else {
+            match BinarySupportedHash::deserialize(deserializer)? {
+                BinarySupportedHash::Blake3(h) => Ok(SupportedHash::Blake3(h)),
+            }
+        }
+    }
+}
+
+struct SupportedHashVisitor;
+
+impl<'de> Visitor<'de> for SupportedHashVisitor {
+    type Value = SupportedHash;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a hash such as \"blake3-...\"")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        v.parse().map_err(E::custom)
+    }
+}
+
 impl Ord for SupportedHash {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         match (self, other) {
@@ -1,7 +1,7 @@
 use std::{
     io::{ErrorKind, Result},
     ops::Deref,
-    os::fd::{FromRawFd, OwnedFd, RawFd},
+    os::fd::{AsRawFd as _, FromRawFd, OwnedFd, RawFd},
     path::{Path, PathBuf},
     sync::atomic::AtomicUsize,
 };
@@ -10,7 +10,7 @@ use nix::{
     errno::Errno,
     libc::{
         syscall, SYS_open_tree, AT_EMPTY_PATH, AT_RECURSIVE, EBADF, OPEN_TREE_CLOEXEC,
-        OPEN_TREE_CLONE, O_CLOEXEC,
+        OPEN_TREE_CLONE,
     },
     NixPath,
 };
@@ -304,6 +304,35 @@ async fn move_replace_fallback(from: &Path, to: &Path) -> Result<()> {
     }
 }
 
+/// Copies a file, sharing its extents with the original when the filesystem supports reflinks.
+///
+/// The destination is created if it doesn't exist, and truncated otherwise.
+#[cfg(target_os = "linux")]
+pub async fn reflink_or_copy(from: &Path, to: &Path) -> Result<()> {
+    let f = from.to_path_buf();
+    let t = to.to_path_buf();
+    let out = tokio::runtime::Handle::current().spawn_blocking(move || {
+        let mut from = std::fs::File::open(f)?;
+        let mut to = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(t)?;
+
+        let cloned =
+            unsafe { nix::libc::ioctl(to.as_raw_fd(), nix::libc::FICLONE, from.as_raw_fd()) };
+        if cloned == 0 {
+            return Ok(());
+        }
+        std::io::copy(&mut from, &mut to).map(|_| ())
+    });
+
+    match out.await {
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+        Ok(result) => result,
+    }
+}
+
 pub fn clone_mount<P: ?Sized + NixPath>(dfd: Option<RawFd>, path: &P) -> nix::Result<OwnedFd> {
     let dfd = dfd.unwrap_or(-EBADF);
     let fd = path.with_nix_path(|path| unsafe {
@@ -1,4 +1,3 @@
-#![feature(inline_const)]
 #![feature(core_io_borrowed_buf)]
 #![feature(read_buf)]
 #![feature(thread_id_value)]
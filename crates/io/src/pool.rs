@@ -377,11 +377,8 @@ mod test {
     pub fn forget() {
         let pool = Pool::<16, u64>::new(&|| 0);
         black_box(pool.take().forget());
-        for i in 0..pool.capacity() {
-            assert_eq!(
-                EMPTY,
-                pool.state.states[i].load(std::sync::atomic::Ordering::SeqCst)
-            );
+        for state in pool.state.states.iter() {
+            assert_eq!(EMPTY, state.load(std::sync::atomic::Ordering::SeqCst));
         }
     }
 
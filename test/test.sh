@@ -23,13 +23,9 @@ upload_file() {
 fetch_file() {
   local src=$1
   local int=$2
-  if [ ! -e "/var/nck/store/files/$int" ]; then
-    fetch "$src" -o /tmp/src
-    local uploaded_file=$(req -X POST "${host}${formula_url}/file" --data-binary "@/tmp/src" -H "If-None-Match: \"${int}\"")
-    awk -v FS=': ' '/^etag/{print $2}' <<< "$uploaded_file" | sed -e 's#^"##' -e 's#"$##'
-  else
-    echo "$int"
-  fi
+  local fetched_file=$(req -X POST "${host}${formula_url}/fetch" --data "{\"url\": \"${src}\", \"hash\": \"${int}\"}" -H "Content-Type: application/json")
+  echo "$fetched_file" >&2
+  awk -v FS=': ' '/^etag/{print $2}' <<< "$fetched_file" | sed -e 's#^"##' -e 's#"$##'
 }
 
 encode() {
@@ -42,6 +38,7 @@ echo "-- $formula_url --" 1>&2
 
 echo "uploading rootfs"
 integrity=$(upload_file "rootfs.tar.gz")
+req -X PUT "$host/api/1/store/roots/bootstrap-rootfs" --data "{\"hash\": \"${integrity}\"}" -H "Content-Type: application/json"
 
 echo "uploading tar"
 tar_url="https://busybox.net/downloads/binaries/1.35.0-x86_64-linux-musl/busybox_TAR"
@@ -104,13 +101,13 @@ request="{
       \"path\": \"/\"
     },
     {
-      \"action\": \"link\",
+      \"action\": \"copy\",
       \"from\": \"/var/nck/store/files/${tar_int}\",
       \"to\": \"/tmp/busybox/tar\",
       \"executable\": true
     },
     {
-      \"action\": \"link\",
+      \"action\": \"copy\",
       \"from\": \"/var/nck/store/files/${gunzip_int}\",
       \"to\": \"/tmp/busybox/gunzip\",
       \"executable\": true